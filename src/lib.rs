@@ -0,0 +1,9 @@
+// The code base predates `dyn` and favours explicit returns and field names.
+#![allow(bare_trait_objects)]
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::needless_late_init)]
+
+extern crate rand;
+
+pub mod math;
+pub mod utils;
+pub mod raytracer;
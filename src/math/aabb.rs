@@ -0,0 +1,95 @@
+use std::f32;
+use math::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Aabb {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+impl Aabb {
+    pub fn new(a: Vector3, b: Vector3) -> Aabb {
+        Aabb {
+            min: min_components(a, b),
+            max: max_components(a, b),
+        }
+    }
+
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vector3::new(f32::MAX, f32::MAX, f32::MAX),
+            max: Vector3::new(-f32::MAX, -f32::MAX, -f32::MAX),
+        }
+    }
+
+    pub fn infinite() -> Aabb {
+        Aabb {
+            min: Vector3::new(-f32::INFINITY, -f32::INFINITY, -f32::INFINITY),
+            max: Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+        }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.min.x.is_finite() && self.min.y.is_finite() && self.min.z.is_finite() &&
+        self.max.x.is_finite() && self.max.y.is_finite() && self.max.z.is_finite()
+    }
+
+    pub fn union(&self, b: Aabb) -> Aabb {
+        Aabb {
+            min: min_components(self.min, b.min),
+            max: max_components(self.max, b.max),
+        }
+    }
+
+    pub fn extend(&self, p: Vector3) -> Aabb {
+        Aabb {
+            min: min_components(self.min, p),
+            max: max_components(self.max, p),
+        }
+    }
+
+    pub fn centroid(&self) -> Vector3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn diagonal(&self) -> Vector3 {
+        self.max - self.min
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.diagonal();
+        if d.x < 0.0 || d.y < 0.0 || d.z < 0.0 {
+            return 0.0;
+        }
+
+        let result = 2.0 * (d.x * d.y + d.y * d.z + d.z * d.x);
+        return result;
+    }
+
+    pub fn maximum_extent(&self) -> usize {
+        let d = self.diagonal();
+        if d.x > d.y && d.x > d.z {
+            0
+        } else if d.y > d.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    // Slab test. `inv_direction` is passed in so it is only computed once per
+    // ray during a traversal. Returns the distance at which the ray enters the
+    // box, if it does so before `t_max`.
+    pub fn intersect(&self, ray: Ray, inv_direction: Vector3, t_max: f32) -> Option<f32> {
+        let t0 = (self.min - ray.origin) * inv_direction;
+        let t1 = (self.max - ray.origin) * inv_direction;
+
+        let t_near = min_components(t0, t1);
+        let t_far = max_components(t0, t1);
+
+        let t_enter = max(max(t_near.x, t_near.y), max(t_near.z, 0.0));
+        let t_exit = min(min(t_far.x, t_far.y), min(t_far.z, t_max));
+
+        if t_enter <= t_exit { Some(t_enter) } else { None }
+    }
+}
@@ -9,7 +9,7 @@ pub struct Color {
 }
 
 impl Color {
-    pub fn new(r: f32, g: f32, b: f32) -> Color {
+    pub const fn new(r: f32, g: f32, b: f32) -> Color {
         Color { red: r, green: g, blue: b }
     }
 
@@ -17,6 +17,23 @@ impl Color {
         Color { red: r, green: r, blue: r }
     }
 
+    pub fn is_black(&self) -> bool {
+        self.red <= 0.0 && self.green <= 0.0 && self.blue <= 0.0
+    }
+
+    pub fn max_component(&self) -> f32 {
+        self.red.max(self.green).max(self.blue)
+    }
+
+    // Perceived brightness (Rec. 709 weights).
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.red + 0.7152 * self.green + 0.0722 * self.blue
+    }
+
+    pub fn exp(&self) -> Color {
+        Color { red: self.red.exp(), green: self.green.exp(), blue: self.blue.exp() }
+    }
+
     pub const BLACK:        Color = Color { red: 0.0, green: 0.0, blue: 0.0 };
     pub const WHITE:        Color = Color { red: 1.0, green: 1.0, blue: 1.0 };
     pub const MIDDLE_GREY:  Color = Color { red: 0.5, green: 0.5, blue: 0.5 };
@@ -46,6 +63,13 @@ impl ops::AddAssign<Color> for Color {
     }
 }
 
+impl ops::Mul<Color> for Color {
+    type Output = Color;
+    fn mul(self, c: Color) -> Color {
+        Color { red: self.red * c.red, green: self.green * c.green, blue: self.blue * c.blue }
+    }
+}
+
 impl ops::Mul<f32> for Color {
     type Output = Color;
     fn mul(self, x: f32) -> Color {
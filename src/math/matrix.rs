@@ -0,0 +1,367 @@
+use std::ops;
+use math::*;
+
+// Row-major 4x4 matrix, transforming column vectors.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Matrix4 {
+    pub m: [[f32; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn new(m: [[f32; 4]; 4]) -> Matrix4 {
+        Matrix4 { m: m }
+    }
+
+    pub fn identity() -> Matrix4 {
+        Matrix4::new([[1.0, 0.0, 0.0, 0.0],
+                      [0.0, 1.0, 0.0, 0.0],
+                      [0.0, 0.0, 1.0, 0.0],
+                      [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    pub fn translation(t: Vector3) -> Matrix4 {
+        Matrix4::new([[1.0, 0.0, 0.0, t.x],
+                      [0.0, 1.0, 0.0, t.y],
+                      [0.0, 0.0, 1.0, t.z],
+                      [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    pub fn scaling(s: Vector3) -> Matrix4 {
+        Matrix4::new([[s.x, 0.0, 0.0, 0.0],
+                      [0.0, s.y, 0.0, 0.0],
+                      [0.0, 0.0, s.z, 0.0],
+                      [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    // Counterclockwise rotation of `angle` degrees around `axis`, looking
+    // from its tip.
+    pub fn rotation(axis: Vector3, angle: f32) -> Matrix4 {
+        let a = safe_normalize(axis);
+        let (s, c) = angle.to_radians().sin_cos();
+        let t = 1.0 - c;
+        Matrix4::new([[t * a.x * a.x + c, t * a.x * a.y - s * a.z, t * a.x * a.z + s * a.y, 0.0],
+                      [t * a.x * a.y + s * a.z, t * a.y * a.y + c, t * a.y * a.z - s * a.x, 0.0],
+                      [t * a.x * a.z - s * a.y, t * a.y * a.z + s * a.x, t * a.z * a.z + c, 0.0],
+                      [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut result = Matrix4::identity();
+        for i in 0..4 {
+            for j in 0..4 {
+                result.m[i][j] = self.m[j][i];
+            }
+        }
+        return result;
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, None for singular
+    // matrices.
+    pub fn inverse(&self) -> Option<Matrix4> {
+        let mut a = self.m;
+        let mut result = Matrix4::identity().m;
+
+        for column in 0..4 {
+            let pivot = (column..4).fold(column, |best, row| {
+                if abs(a[row][column]) > abs(a[best][column]) { row } else { best }
+            });
+            if abs(a[pivot][column]) < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            result.swap(column, pivot);
+
+            let inv_pivot = 1.0 / a[column][column];
+            for j in 0..4 {
+                a[column][j] *= inv_pivot;
+                result[column][j] *= inv_pivot;
+            }
+
+            for row in 0..4 {
+                let factor = a[row][column];
+                if row == column || factor == 0.0 {
+                    continue;
+                }
+                for j in 0..4 {
+                    a[row][j] -= factor * a[column][j];
+                    result[row][j] -= factor * result[column][j];
+                }
+            }
+        }
+
+        return Some(Matrix4::new(result));
+    }
+
+    pub fn transform_point(&self, p: Vector3) -> Vector3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+        let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        let result = Vector3::new(x, y, z);
+        return if w == 1.0 { result } else { result / w };
+    }
+
+    pub fn transform_vector(&self, v: Vector3) -> Vector3 {
+        let m = &self.m;
+        Vector3::new(m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+                     m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+                     m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z)
+    }
+
+    // Determinant of the upper 3x3 part, the change of volume of affine
+    // transforms.
+    pub fn determinant3(&self) -> f32 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) -
+        m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0]) +
+        m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+}
+
+impl ops::Mul<Matrix4> for Matrix4 {
+    type Output = Matrix4;
+    fn mul(self, b: Matrix4) -> Matrix4 {
+        let mut result = Matrix4::new([[0.0; 4]; 4]);
+        for i in 0..4 {
+            for j in 0..4 {
+                result.m[i][j] = (0..4).map(|k| self.m[i][k] * b.m[k][j]).sum();
+            }
+        }
+        return result;
+    }
+}
+
+// Invertible affine transform, keeping its inverse around to bring rays
+// and normals from one space to the other.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Transform {
+    matrix: Matrix4,
+    inverse: Matrix4,
+}
+
+impl Transform {
+    // None for singular matrices.
+    pub fn new(matrix: Matrix4) -> Option<Transform> {
+        let inverse = matrix.inverse()?;
+        Some(Transform { matrix: matrix, inverse: inverse })
+    }
+
+    pub fn identity() -> Transform {
+        Transform { matrix: Matrix4::identity(), inverse: Matrix4::identity() }
+    }
+
+    pub fn translate(t: Vector3) -> Transform {
+        Transform { matrix: Matrix4::translation(t), inverse: Matrix4::translation(-t) }
+    }
+
+    // Every component of `s` must be non-zero.
+    pub fn scale(s: Vector3) -> Transform {
+        Transform {
+            matrix: Matrix4::scaling(s),
+            inverse: Matrix4::scaling(Vector3::new(1.0 / s.x, 1.0 / s.y, 1.0 / s.z)),
+        }
+    }
+
+    // See `Matrix4::rotation`.
+    pub fn rotate(axis: Vector3, angle: f32) -> Transform {
+        let matrix = Matrix4::rotation(axis, angle);
+        Transform { matrix: matrix, inverse: matrix.transpose() }
+    }
+
+    // This transform followed by `next`.
+    pub fn then(&self, next: Transform) -> Transform {
+        Transform {
+            matrix: next.matrix * self.matrix,
+            inverse: self.inverse * next.inverse,
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform { matrix: self.inverse, inverse: self.matrix }
+    }
+
+    // Whether the transform only translates, rotates, mirrors and scales
+    // the same along every axis, keeping angles and ratios of lengths.
+    pub fn is_similarity(&self) -> bool {
+        let x = self.vector(Vector3::unit_x());
+        let y = self.vector(Vector3::unit_y());
+        let z = self.vector(Vector3::unit_z());
+        let scale = length_squared(x);
+        let tolerance = 1e-4 * scale;
+
+        abs(length_squared(y) - scale) <= tolerance && abs(length_squared(z) - scale) <= tolerance &&
+            abs(dot(x, y)) <= tolerance && abs(dot(y, z)) <= tolerance && abs(dot(z, x)) <= tolerance
+    }
+
+    pub fn matrix(&self) -> &Matrix4 {
+        &self.matrix
+    }
+
+    pub fn point(&self, p: Vector3) -> Vector3 {
+        self.matrix.transform_point(p)
+    }
+
+    pub fn vector(&self, v: Vector3) -> Vector3 {
+        self.matrix.transform_vector(v)
+    }
+
+    // Normals go through the inverse transpose to stay orthogonal to the
+    // surface under non-uniform scaling. The result isn't normalized.
+    pub fn normal(&self, n: Vector3) -> Vector3 {
+        self.inverse.transpose().transform_vector(n)
+    }
+
+    // `ray` in the space this transform leads to. Its direction is
+    // normalized again, so distances along it change with the scale.
+    pub fn ray(&self, ray: Ray) -> Ray {
+        Ray::new(self.point(ray.origin), self.vector(ray.direction)).with_time(ray.time)
+    }
+
+    // Translation, rotation and stretch, a symmetric matrix, which applied
+    // in reverse order make up the transform. Transforms are interpolated
+    // through them.
+    pub fn decompose(&self) -> (Vector3, Quaternion, Matrix4) {
+        let m = &self.matrix.m;
+        let translation = Vector3::new(m[0][3], m[1][3], m[2][3]);
+
+        let mut linear = self.matrix;
+        for i in 0..3 {
+            linear.m[i][3] = 0.0;
+        }
+
+        // Polar decomposition: averaging the matrix with its inverse
+        // transpose converges to the closest rotation.
+        let mut rotation = linear;
+        for _ in 0..100 {
+            let inverse_transpose = match rotation.inverse() {
+                Some(inverse) => inverse.transpose(),
+                None => break,
+            };
+
+            let mut next = rotation;
+            let mut change: f32 = 0.0;
+            for i in 0..3 {
+                for j in 0..3 {
+                    next.m[i][j] = 0.5 * (rotation.m[i][j] + inverse_transpose.m[i][j]);
+                    change = change.max(abs(next.m[i][j] - rotation.m[i][j]));
+                }
+            }
+            rotation = next;
+            if change < 1e-6 {
+                break;
+            }
+        }
+
+        // Mirror images leave a reflection, which the stretch takes over.
+        if rotation.determinant3() < 0.0 {
+            for i in 0..3 {
+                for j in 0..3 {
+                    rotation.m[i][j] = -rotation.m[i][j];
+                }
+            }
+        }
+
+        let stretch = rotation.transpose() * linear;
+        return (translation, Quaternion::from_matrix(&rotation), stretch);
+    }
+
+    // Inverse of `decompose`, None if the stretch is singular.
+    pub fn compose(translation: Vector3, rotation: Quaternion, stretch: Matrix4) -> Option<Transform> {
+        Transform::new(Matrix4::translation(translation) * rotation.to_matrix() * stretch)
+    }
+
+    // Box around the 8 transformed corners of `b`, infinite boxes stay so.
+    pub fn bounds(&self, b: Aabb) -> Aabb {
+        if !b.is_finite() {
+            return b;
+        }
+
+        let mut result = Aabb::empty();
+        for i in 0..8 {
+            let corner = Vector3::new(if i & 1 == 0 { b.min.x } else { b.max.x },
+                                      if i & 2 == 0 { b.min.y } else { b.max.y },
+                                      if i & 4 == 0 { b.min.z } else { b.max.z });
+            result = result.extend(self.point(corner));
+        }
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vector3, b: Vector3) {
+        assert!(length(a - b) < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn inverse_undoes_the_matrix() {
+        let m = Matrix4::new([[2.0, 0.5, 0.0, 1.0],
+                              [0.0, 1.0, -3.0, 2.0],
+                              [1.0, 0.0, 4.0, -1.0],
+                              [0.0, 0.0, 0.0, 1.0]]);
+        let product = m * m.inverse().unwrap();
+        for i in 0..4 {
+            for j in 0..4 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!(abs(product.m[i][j] - expected) < 1e-5, "{:?}", product);
+            }
+        }
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        let m = Matrix4::scaling(Vector3::new(1.0, 0.0, 1.0));
+        assert!(m.inverse().is_none());
+        assert!(Transform::new(m).is_none());
+    }
+
+    #[test]
+    fn then_applies_self_first() {
+        let t = Transform::scale(Vector3::new(2.0, 2.0, 2.0)).then(Transform::translate(Vector3::new(1.0, 0.0, 0.0)));
+        assert_close(t.point(Vector3::new(1.0, 1.0, 1.0)), Vector3::new(3.0, 2.0, 2.0));
+        assert_close(t.inverse().point(Vector3::new(3.0, 2.0, 2.0)), Vector3::new(1.0, 1.0, 1.0));
+
+        let r = Transform::rotate(Vector3::unit_z(), 90.0).then(Transform::translate(Vector3::new(0.0, 0.0, 1.0)));
+        assert_close(r.point(Vector3::new(1.0, 0.0, 0.0)), Vector3::new(0.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn normals_stay_orthogonal_under_non_uniform_scale() {
+        let t = Transform::scale(Vector3::new(4.0, 1.0, 1.0)).then(Transform::rotate(Vector3::new(1.0, 1.0, 0.0), 30.0));
+        let tangent = Vector3::new(1.0, -1.0, 0.0);
+        let normal = Vector3::new(1.0, 1.0, 0.0);
+        assert!(abs(dot(t.vector(tangent), t.normal(normal))) < 1e-4);
+    }
+
+    #[test]
+    fn decompose_then_compose_gives_the_transform_back() {
+        let t = Transform::scale(Vector3::new(2.0, -1.0, 0.5))
+            .then(Transform::rotate(Vector3::new(1.0, 2.0, 3.0), 70.0))
+            .then(Transform::translate(Vector3::new(1.0, -2.0, 3.0)));
+        let (translation, rotation, stretch) = t.decompose();
+        let composed = Transform::compose(translation, rotation, stretch).unwrap();
+
+        assert_close(translation, Vector3::new(1.0, -2.0, 3.0));
+        for p in [Vector3::unit_x(), Vector3::unit_z(), Vector3::new(1.0, 2.0, -3.0)].iter() {
+            assert_close(composed.point(*p), t.point(*p));
+        }
+    }
+
+    #[test]
+    fn similarities_keep_angles() {
+        let rotated = Transform::scale(Vector3::new(2.0, 2.0, -2.0)).then(Transform::rotate(Vector3::unit_x(), 30.0));
+        assert!(rotated.then(Transform::translate(Vector3::unit_z())).is_similarity());
+        assert!(!rotated.then(Transform::scale(Vector3::new(1.0, 3.0, 1.0))).is_similarity());
+    }
+
+    #[test]
+    fn ray_keeps_its_time() {
+        let ray = Ray::new(Vector3::zero(), Vector3::unit_x()).with_time(0.25);
+        let moved = Transform::scale(Vector3::new(3.0, 1.0, 1.0)).ray(ray);
+        assert_eq!(moved.time, 0.25);
+        assert_close(moved.direction, Vector3::unit_x());
+    }
+}
@@ -12,6 +12,21 @@ pub use self::color::*;
 pub mod ray;
 pub use self::ray::*;
 
+pub mod aabb;
+pub use self::aabb::*;
+
+pub mod matrix;
+pub use self::matrix::*;
+
+pub mod quaternion;
+pub use self::quaternion::*;
+
+pub mod sampling;
+pub use self::sampling::*;
+
+pub mod noise;
+pub use self::noise::*;
+
 #[inline]
 #[allow(dead_code)]
 pub fn sqrt(x: f32) -> f32 {
@@ -82,6 +97,13 @@ pub fn atan(x: f32) -> f32 {
     return result;
 }
 
+#[inline]
+#[allow(dead_code)]
+pub fn atan2(y: f32, x: f32) -> f32 {
+    let result = y.atan2(x);
+    return result;
+}
+
 #[inline]
 #[allow(dead_code)]
 pub fn square(x: f32) -> f32 {
@@ -0,0 +1,91 @@
+use std::f32;
+
+use math::*;
+
+// Pseudo-random 32 bit value for a lattice point, replacing the permutation
+// table of the reference implementation.
+fn hash(x: i32, y: i32, z: i32, seed: u32) -> u32 {
+    let mut h = seed.wrapping_mul(0x9e37_79b9) ^ 0x85eb_ca6b;
+    for &v in [x, y, z].iter() {
+        h ^= v as u32;
+        h = h.wrapping_mul(0x27d4_eb2d);
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x8553_1b97);
+        h ^= h >> 13;
+    }
+    return h;
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
+
+// Dot product of the offset `d` from a lattice point with one of the 12
+// gradients of improved Perlin noise, picked by `h` (four of them twice).
+fn gradient(h: u32, d: Vector3) -> f32 {
+    let h = h & 15;
+    let u = if h < 8 { d.x } else { d.y };
+    let v = if h < 4 { d.y } else if h == 12 || h == 14 { d.x } else { d.z };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+// Improved Perlin noise (Perlin, 2002), roughly in [-1, 1] and zero at
+// integer coordinates.
+pub fn perlin(p: Vector3) -> f32 {
+    let cell = Vector3::new(floor(p.x), floor(p.y), floor(p.z));
+    let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+    let d = p - cell;
+
+    let g = |i: i32, j: i32, k: i32| {
+        gradient(hash(x + i, y + j, z + k, 0), d - Vector3::new(i as f32, j as f32, k as f32))
+    };
+
+    let (u, v, w) = (fade(d.x), fade(d.y), fade(d.z));
+    lerp(w, lerp(v, lerp(u, g(0, 0, 0), g(1, 0, 0)),
+                    lerp(u, g(0, 1, 0), g(1, 1, 0))),
+            lerp(v, lerp(u, g(0, 0, 1), g(1, 0, 1)),
+                    lerp(u, g(0, 1, 1), g(1, 1, 1))))
+}
+
+// Sum of `octaves` layers of Perlin noise, each twice the frequency and half
+// the amplitude of the previous one, normalized to about [-1, 1].
+pub fn fbm(p: Vector3, octaves: u32) -> f32 {
+    let mut result = 0.0;
+    let mut amplitude = 1.0;
+    let mut total = 0.0;
+    let mut p = p;
+    for _ in 0..octaves {
+        result += amplitude * perlin(p);
+        total += amplitude;
+        amplitude *= 0.5;
+        p *= 2.0;
+    }
+
+    return if total > 0.0 { result / total } else { 0.0 };
+}
+
+// Cellular noise (Worley, 1996): distance from `p` to the closest of the
+// points scattered one per unit cell.
+pub fn worley(p: Vector3) -> f32 {
+    let cell = Vector3::new(floor(p.x), floor(p.y), floor(p.z));
+    let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+
+    let mut result = f32::MAX;
+    for i in -1..2 {
+        for j in -1..2 {
+            for k in -1..2 {
+                let to_unit = |seed: u32| hash(x + i, y + j, z + k, seed) as f32 / 4_294_967_296.0;
+                let point = Vector3::new((x + i) as f32 + to_unit(1),
+                                         (y + j) as f32 + to_unit(2),
+                                         (z + k) as f32 + to_unit(3));
+                result = min(result, length_squared(point - p));
+            }
+        }
+    }
+
+    return sqrt(result);
+}
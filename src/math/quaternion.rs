@@ -0,0 +1,125 @@
+use std::ops;
+use math::*;
+
+// Unit quaternions stand for rotations, which can then be interpolated
+// along the shortest arc between them.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Quaternion {
+    pub v: Vector3,
+    pub w: f32,
+}
+
+impl Quaternion {
+    pub fn new(v: Vector3, w: f32) -> Quaternion {
+        Quaternion { v: v, w: w }
+    }
+
+    pub fn identity() -> Quaternion {
+        Quaternion::new(Vector3::zero(), 1.0)
+    }
+
+    // Rotation of the upper 3x3 part of `m`, which must be a rotation
+    // matrix.
+    pub fn from_matrix(m: &Matrix4) -> Quaternion {
+        let m = &m.m;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+
+        // Divides by the largest of the four components to stay accurate.
+        let result = if trace > 0.0 {
+            let s = 2.0 * sqrt(trace + 1.0);
+            Quaternion::new(Vector3::new(m[2][1] - m[1][2], m[0][2] - m[2][0], m[1][0] - m[0][1]) / s, 0.25 * s)
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = 2.0 * sqrt(1.0 + m[0][0] - m[1][1] - m[2][2]);
+            Quaternion::new(Vector3::new(0.25 * s, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s),
+                            (m[2][1] - m[1][2]) / s)
+        } else if m[1][1] > m[2][2] {
+            let s = 2.0 * sqrt(1.0 + m[1][1] - m[0][0] - m[2][2]);
+            Quaternion::new(Vector3::new((m[0][1] + m[1][0]) / s, 0.25 * s, (m[1][2] + m[2][1]) / s),
+                            (m[0][2] - m[2][0]) / s)
+        } else {
+            let s = 2.0 * sqrt(1.0 + m[2][2] - m[0][0] - m[1][1]);
+            Quaternion::new(Vector3::new((m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, 0.25 * s),
+                            (m[1][0] - m[0][1]) / s)
+        };
+        return result.normalize();
+    }
+
+    pub fn to_matrix(&self) -> Matrix4 {
+        let (x, y, z, w) = (self.v.x, self.v.y, self.v.z, self.w);
+        Matrix4::new([[1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w), 0.0],
+                      [2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w), 0.0],
+                      [2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y), 0.0],
+                      [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    pub fn dot(&self, q: Quaternion) -> f32 {
+        dot(self.v, q.v) + self.w * q.w
+    }
+
+    pub fn normalize(&self) -> Quaternion {
+        *self * (1.0 / sqrt(self.dot(*self)))
+    }
+
+    // Rotation a fraction `t` of the way from `self` to `q`, at a constant
+    // angular speed.
+    pub fn slerp(&self, q: Quaternion, t: f32) -> Quaternion {
+        // q and -q are the same rotation, the closest one is the shortest way.
+        let mut q = q;
+        let mut cos_theta = self.dot(q);
+        if cos_theta < 0.0 {
+            q = -q;
+            cos_theta = -cos_theta;
+        }
+
+        // Nearly the same rotation, the arc is as good as straight.
+        if cos_theta > 0.9995 {
+            return (*self * (1.0 - t) + q * t).normalize();
+        }
+
+        let theta = acos(clamp(cos_theta, -1.0, 1.0)) * t;
+        let perpendicular = (q - *self * cos_theta).normalize();
+        return *self * cos(theta) + perpendicular * sin(theta);
+    }
+}
+
+impl ops::Add for Quaternion {
+    type Output = Quaternion;
+    fn add(self, q: Quaternion) -> Quaternion {
+        Quaternion::new(self.v + q.v, self.w + q.w)
+    }
+}
+
+impl ops::Sub for Quaternion {
+    type Output = Quaternion;
+    fn sub(self, q: Quaternion) -> Quaternion {
+        Quaternion::new(self.v - q.v, self.w - q.w)
+    }
+}
+
+impl ops::Mul<f32> for Quaternion {
+    type Output = Quaternion;
+    fn mul(self, s: f32) -> Quaternion {
+        Quaternion::new(self.v * s, self.w * s)
+    }
+}
+
+impl ops::Neg for Quaternion {
+    type Output = Quaternion;
+    fn neg(self) -> Quaternion {
+        Quaternion::new(-self.v, -self.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slerp_rotates_at_constant_speed() {
+        let a = Quaternion::from_matrix(&Matrix4::identity());
+        let b = Quaternion::from_matrix(&Matrix4::rotation(Vector3::unit_z(), 120.0));
+        let half = a.slerp(b, 0.5).to_matrix().transform_vector(Vector3::unit_x());
+        let expected = Matrix4::rotation(Vector3::unit_z(), 60.0).transform_vector(Vector3::unit_x());
+        assert!(length(half - expected) < 1e-4, "{:?} != {:?}", half, expected);
+    }
+}
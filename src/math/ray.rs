@@ -4,6 +4,9 @@ use math::*;
 pub struct Ray {
     pub origin: Vector3,
     pub direction: Vector3,
+    // Instant the ray is traced at, between the camera's shutter open and
+    // close times.
+    pub time: f32,
 }
 
 impl Ray {
@@ -11,8 +14,14 @@ impl Ray {
         Ray {
             origin: origin,
             direction: safe_normalize(direction),
+            time: 0.0,
         }
     }
+
+    pub fn with_time(mut self, time: f32) -> Ray {
+        self.time = time;
+        self
+    }
 }
 
 #[inline]
@@ -0,0 +1,159 @@
+use std::f32;
+
+use math::*;
+
+// Cosine weighted direction around +z, its density is cos(theta) / pi.
+pub fn cosine_sample_hemisphere(u: Vector2) -> Vector3 {
+    let r = sqrt(u.x);
+    let phi = 2.0 * f32::consts::PI * u.y;
+    let z = sqrt(max(0.0, 1.0 - u.x));
+
+    Vector3::new(r * cos(phi), r * sin(phi), z)
+}
+
+// Uniformly distributed direction.
+pub fn uniform_sample_sphere(u: Vector2) -> Vector3 {
+    let z = 1.0 - 2.0 * u.x;
+    let r = sqrt(max(0.0, 1.0 - square(z)));
+    let phi = 2.0 * f32::consts::PI * u.y;
+
+    Vector3::new(r * cos(phi), r * sin(phi), z)
+}
+
+// Point uniformly distributed over the unit disk, mapping concentric squares
+// to concentric circles to keep strata compact (Shirley & Chiu, 1997).
+pub fn concentric_sample_disk(u: Vector2) -> Vector2 {
+    let x = 2.0 * u.x - 1.0;
+    let y = 2.0 * u.y - 1.0;
+    if x == 0.0 && y == 0.0 {
+        return Vector2::zero();
+    }
+
+    let (r, theta) = if abs(x) > abs(y) {
+        (x, f32::consts::FRAC_PI_4 * (y / x))
+    } else {
+        (y, f32::consts::FRAC_PI_2 - f32::consts::FRAC_PI_4 * (x / y))
+    };
+
+    Vector2::new(r * cos(theta), r * sin(theta))
+}
+
+// Barycentric coordinates of a point uniformly distributed over a triangle.
+pub fn uniform_sample_triangle(u: Vector2) -> [f32; 3] {
+    let s = sqrt(u.x);
+    let b1 = 1.0 - s;
+    let b2 = u.y * s;
+
+    [1.0 - b1 - b2, b1, b2]
+}
+
+// Multiple importance sampling weight of a sample drawn with density `f_pdf`
+// when it could also have been drawn with density `g_pdf` (Veach, power 2).
+pub fn power_heuristic(f_pdf: f32, g_pdf: f32) -> f32 {
+    let f = square(f_pdf);
+    let g = square(g_pdf);
+    if f + g <= 0.0 {
+        return 0.0;
+    }
+
+    return f / (f + g);
+}
+
+// Piecewise constant distribution over [0, 1), with one segment per value of
+// the function.
+#[derive(Clone)]
+pub struct Distribution1D {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+impl Distribution1D {
+    // `func` must not be negative. When it is zero everywhere the
+    // distribution is uniform.
+    pub fn new(func: Vec<f32>) -> Distribution1D {
+        let n = func.len();
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.0);
+        for i in 0..n {
+            let previous = cdf[i];
+            cdf.push(previous + func[i] / n as f32);
+        }
+
+        let integral = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 { *c / integral } else { i as f32 / n as f32 };
+        }
+
+        Distribution1D {
+            func: func,
+            cdf: cdf,
+            integral: integral,
+        }
+    }
+
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    // Maps `u` to a point of [0, 1), returned with its density and the index
+    // of the segment it falls in.
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let n = self.func.len();
+        let segment = self.cdf.partition_point(|&c| c <= u).saturating_sub(1);
+        let segment = ::std::cmp::min(segment, n - 1);
+
+        let width = self.cdf[segment + 1] - self.cdf[segment];
+        let du = if width > 0.0 { (u - self.cdf[segment]) / width } else { 0.0 };
+
+        let x = min((segment as f32 + du) / n as f32, 0.99999994);
+        return (x, self.pdf(segment), segment);
+    }
+
+    // Density of the points of the segment `index`.
+    pub fn pdf(&self, index: usize) -> f32 {
+        if self.integral > 0.0 { self.func[index] / self.integral } else { 1.0 }
+    }
+}
+
+// Piecewise constant distribution over [0, 1)^2, sampled by picking a row
+// first, then a column within it.
+#[derive(Clone)]
+pub struct Distribution2D {
+    width: usize,
+    height: usize,
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    // `func` is stored row after row, top row first.
+    pub fn new(func: &[f32], width: usize, height: usize) -> Distribution2D {
+        assert_eq!(func.len(), width * height);
+
+        let rows: Vec<Distribution1D> = func.chunks(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral()).collect());
+
+        Distribution2D {
+            width: width,
+            height: height,
+            rows: rows,
+            marginal: marginal,
+        }
+    }
+
+    // Point of [0, 1)^2 along with its density.
+    pub fn sample(&self, u: Vector2) -> (Vector2, f32) {
+        let (y, pdf_y, row) = self.marginal.sample(u.y);
+        let (x, pdf_x, _) = self.rows[row].sample(u.x);
+        (Vector2::new(x, y), pdf_x * pdf_y)
+    }
+
+    pub fn pdf(&self, p: Vector2) -> f32 {
+        let x = clamp(floor(p.x * self.width as f32), 0.0, (self.width - 1) as f32) as usize;
+        let y = clamp(floor(p.y * self.height as f32), 0.0, (self.height - 1) as f32) as usize;
+        self.rows[y].pdf(x) * self.marginal.pdf(y)
+    }
+}
@@ -21,6 +21,18 @@ impl Vector3 {
     pub fn unit_z() -> Vector3 { Vector3 { x: 0.0, y: 0.0, z: 1.0, } }
 }
 
+impl ops::Index<usize> for Vector3 {
+    type Output = f32;
+    fn index(&self, i: usize) -> &f32 {
+        match i {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vector3 index out of range: {}", i),
+        }
+    }
+}
+
 impl ops::Neg for Vector3 {
     type Output = Vector3;
     fn neg(self) -> Vector3 {
@@ -146,4 +158,120 @@ pub fn safe_normalize(v: Vector3) -> Vector3 {
     }
 
     return result;
-}
\ No newline at end of file
+}
+
+// Mirrors `v` about `n`, both pointing away from the surface.
+pub fn reflect(v: Vector3, n: Vector3) -> Vector3 {
+    2.0 * dot(v, n) * n - v
+}
+
+// Direction refracted through a surface of normal `n` from `v`, both pointing
+// away from the surface, `eta` being the index of refraction on the side `n`
+// points away from over the one it points to. None on total internal
+// reflection, otherwise the relative index of refraction actually crossed.
+pub fn refract(v: Vector3, n: Vector3, eta: f32) -> Option<(Vector3, f32)> {
+    let mut n = n;
+    let mut eta = eta;
+    let mut cos_i = dot(n, v);
+    if cos_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_i = -cos_i;
+        n = -n;
+    }
+
+    let sin2_i = max(0.0, 1.0 - square(cos_i));
+    let sin2_t = sin2_i / square(eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+
+    let cos_t = sqrt(1.0 - sin2_t);
+    let result = -v / eta + (cos_i / eta - cos_t) * n;
+    return Some((result, eta));
+}
+
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub struct Vector2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Vector2 {
+    pub fn new(x: f32, y: f32) -> Vector2 {
+        Vector2 { x: x, y: y }
+    }
+
+    pub fn zero() -> Vector2 { Vector2 { x: 0.0, y: 0.0 } }
+}
+
+impl ops::Add<Vector2> for Vector2 {
+    type Output = Vector2;
+    fn add(self, b: Vector2) -> Vector2 {
+        Vector2 { x: self.x + b.x, y: self.y + b.y }
+    }
+}
+
+impl ops::Sub<Vector2> for Vector2 {
+    type Output = Vector2;
+    fn sub(self, b: Vector2) -> Vector2 {
+        Vector2 { x: self.x - b.x, y: self.y - b.y }
+    }
+}
+
+impl ops::Mul<f32> for Vector2 {
+    type Output = Vector2;
+    fn mul(self, x: f32) -> Vector2 {
+        Vector2 { x: self.x * x, y: self.y * x }
+    }
+}
+
+impl ops::Mul<Vector2> for f32 {
+    type Output = Vector2;
+    fn mul(self, v: Vector2) -> Vector2 {
+        Vector2 { x: self * v.x, y: self * v.y }
+    }
+}
+
+pub fn min_components(a: Vector3, b: Vector3) -> Vector3 {
+    Vector3::new(min(a.x, b.x), min(a.y, b.y), min(a.z, b.z))
+}
+
+pub fn max_components(a: Vector3, b: Vector3) -> Vector3 {
+    Vector3::new(max(a.x, b.x), max(a.y, b.y), max(a.z, b.z))
+}
+
+// Builds two unit vectors orthogonal to `n` (assumed normalized) and to each
+// other (Duff et al., 2017).
+pub fn coordinate_system(n: Vector3) -> (Vector3, Vector3) {
+    let sign = if n.z >= 0.0 { 1.0 } else { -1.0 };
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+
+    let t = Vector3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x);
+    let s = Vector3::new(b, sign + n.y * n.y * a, -n.y);
+    return (t, s);
+}
+
+// Orthonormal basis whose z axis is a surface normal, used to express
+// directions relative to the surface.
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    pub x: Vector3,
+    pub y: Vector3,
+    pub z: Vector3,
+}
+
+impl Frame {
+    pub fn from_normal(n: Vector3) -> Frame {
+        let (x, y) = coordinate_system(n);
+        Frame { x: x, y: y, z: n }
+    }
+
+    pub fn to_local(&self, v: Vector3) -> Vector3 {
+        Vector3::new(dot(v, self.x), dot(v, self.y), dot(v, self.z))
+    }
+
+    pub fn to_world(&self, v: Vector3) -> Vector3 {
+        v.x * self.x + v.y * self.y + v.z * self.z
+    }
+}
@@ -0,0 +1,558 @@
+use std::f32;
+
+use math::*;
+
+// Directions given to and returned by a `Bsdf` are expressed in the shading
+// frame, where the surface normal is +z, and point away from the surface.
+// `wo` is the direction light leaves towards (the viewer), `wi` the one it
+// arrives from.
+pub struct BsdfSample {
+    pub wi: Vector3,
+    pub f: Color,
+    // Solid angle density of `wi`, or the probability of picking it for
+    // specular samples.
+    pub pdf: f32,
+    // Sampled from a delta distribution, `f` and `pdf` are then only
+    // meaningful as a ratio.
+    pub is_specular: bool,
+}
+
+impl BsdfSample {
+    // Contribution of the sample, f |cos(theta_i)| / pdf.
+    pub fn weight(&self) -> Color {
+        self.f * (abs(self.wi.z) / self.pdf)
+    }
+}
+
+pub trait Bsdf: Send + Sync {
+    // Fraction of the radiance arriving from `wi` scattered towards `wo`.
+    fn eval(&self, wo: Vector3, wi: Vector3) -> Color;
+
+    // Picks `wi` proportionally to the BSDF as much as possible. `uc` and `u`
+    // are uniformly distributed in [0, 1) and [0, 1)^2.
+    fn sample(&self, wo: Vector3, uc: f32, u: Vector2) -> Option<BsdfSample>;
+
+    // Solid angle density with which `sample` picks `wi`.
+    fn pdf(&self, wo: Vector3, wi: Vector3) -> f32;
+
+    // Whether the BSDF only scatters in discrete directions, `eval` and `pdf`
+    // are then always zero and light sampling is pointless.
+    fn is_specular(&self) -> bool { false }
+}
+
+fn same_hemisphere(a: Vector3, b: Vector3) -> bool {
+    a.z * b.z > 0.0
+}
+
+// Roughness below which microfacet BSDFs are treated as perfectly smooth.
+const SMOOTH_ROUGHNESS: f32 = 1e-3;
+
+// Fresnel reflectance of a dielectric interface, `eta` being the index of
+// refraction below the surface over the one above.
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let mut cos_i = clamp(cos_i, -1.0, 1.0);
+    let mut eta = eta;
+    if cos_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_i = -cos_i;
+    }
+
+    let sin2_i = 1.0 - square(cos_i);
+    let sin2_t = sin2_i / square(eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = sqrt(max(0.0, 1.0 - sin2_t));
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    return (square(r_parallel) + square(r_perpendicular)) * 0.5;
+}
+
+// Fresnel reflectance of a conductor of complex index of refraction eta + ik,
+// for a single wavelength.
+fn fresnel_conductor_channel(cos_i: f32, eta: f32, k: f32) -> f32 {
+    let cos2_i = square(clamp(cos_i, -1.0, 1.0));
+    let sin2_i = 1.0 - cos2_i;
+    let eta2 = square(eta);
+    let k2 = square(k);
+
+    let t0 = eta2 - k2 - sin2_i;
+    let a2_plus_b2 = sqrt(max(0.0, square(t0) + 4.0 * eta2 * k2));
+    let t1 = a2_plus_b2 + cos2_i;
+    let a = sqrt(max(0.0, 0.5 * (a2_plus_b2 + t0)));
+    let t2 = 2.0 * abs(cos_i) * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2_i * a2_plus_b2 + square(sin2_i);
+    let t4 = t2 * sin2_i;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    return 0.5 * (rp + rs);
+}
+
+pub fn fresnel_conductor(cos_i: f32, eta: Color, k: Color) -> Color {
+    Color::new(fresnel_conductor_channel(cos_i, eta.red, k.red),
+               fresnel_conductor_channel(cos_i, eta.green, k.green),
+               fresnel_conductor_channel(cos_i, eta.blue, k.blue))
+}
+
+// Isotropic GGX (Trowbridge-Reitz) distribution of microfacet normals.
+#[derive(Clone, Copy)]
+struct Ggx {
+    alpha: f32,
+}
+
+impl Ggx {
+    // `roughness` is perceptually linear, alpha is its square.
+    fn new(roughness: f32) -> Ggx {
+        Ggx { alpha: max(square(roughness), 1e-4) }
+    }
+
+    fn d(&self, wm: Vector3) -> f32 {
+        let cos2_theta = square(wm.z);
+        if cos2_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let tan2_theta = (1.0 - cos2_theta) / cos2_theta;
+        let e = 1.0 + tan2_theta / square(self.alpha);
+        return 1.0 / (f32::consts::PI * square(self.alpha) * square(cos2_theta) * square(e));
+    }
+
+    fn lambda(&self, w: Vector3) -> f32 {
+        let cos2_theta = square(w.z);
+        if cos2_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let tan2_theta = (1.0 - cos2_theta) / cos2_theta;
+        return (sqrt(1.0 + square(self.alpha) * tan2_theta) - 1.0) * 0.5;
+    }
+
+    fn g1(&self, w: Vector3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    fn g(&self, wo: Vector3, wi: Vector3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Density of the normals visible from `w`.
+    fn d_visible(&self, w: Vector3, wm: Vector3) -> f32 {
+        if w.z == 0.0 {
+            return 0.0;
+        }
+
+        self.g1(w) / abs(w.z) * self.d(wm) * abs(dot(w, wm))
+    }
+
+    // Samples a normal proportionally to `d_visible` (Heitz 2018).
+    fn sample_wm(&self, w: Vector3, u: Vector2) -> Vector3 {
+        let mut wh = normalize(Vector3::new(self.alpha * w.x, self.alpha * w.y, w.z));
+        if wh.z < 0.0 {
+            wh = -wh;
+        }
+
+        let t1 = if wh.z < 0.99999 { normalize(cross(Vector3::unit_z(), wh)) } else { Vector3::unit_x() };
+        let t2 = cross(wh, t1);
+
+        let r = sqrt(u.x);
+        let phi = 2.0 * f32::consts::PI * u.y;
+        let px = r * cos(phi);
+        let mut py = r * sin(phi);
+
+        let h = sqrt(max(0.0, 1.0 - square(px)));
+        let s = (1.0 + wh.z) * 0.5;
+        py = (1.0 - s) * h + s * py;
+        let pz = sqrt(max(0.0, 1.0 - square(px) - square(py)));
+
+        let nh = px * t1 + py * t2 + pz * wh;
+        return normalize(Vector3::new(self.alpha * nh.x, self.alpha * nh.y, max(1e-6, nh.z)));
+    }
+}
+
+#[derive(Clone)]
+pub struct Lambertian {
+    albedo: Color,
+}
+
+impl Lambertian {
+    pub fn new(albedo: Color) -> Lambertian {
+        Lambertian { albedo: albedo }
+    }
+}
+
+impl Bsdf for Lambertian {
+    fn eval(&self, wo: Vector3, wi: Vector3) -> Color {
+        if !same_hemisphere(wo, wi) {
+            return Color::BLACK;
+        }
+
+        self.albedo / f32::consts::PI
+    }
+
+    fn sample(&self, wo: Vector3, _uc: f32, u: Vector2) -> Option<BsdfSample> {
+        let mut wi = cosine_sample_hemisphere(u);
+        if wo.z < 0.0 {
+            wi.z = -wi.z;
+        }
+
+        let pdf = self.pdf(wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            wi: wi,
+            f: self.eval(wo, wi),
+            pdf: pdf,
+            is_specular: false,
+        })
+    }
+
+    fn pdf(&self, wo: Vector3, wi: Vector3) -> f32 {
+        if !same_hemisphere(wo, wi) {
+            return 0.0;
+        }
+
+        abs(wi.z) / f32::consts::PI
+    }
+}
+
+#[derive(Clone)]
+pub struct Mirror {
+    reflectance: Color,
+}
+
+impl Mirror {
+    pub fn new(reflectance: Color) -> Mirror {
+        Mirror { reflectance: reflectance }
+    }
+}
+
+impl Bsdf for Mirror {
+    fn eval(&self, _wo: Vector3, _wi: Vector3) -> Color {
+        Color::BLACK
+    }
+
+    fn sample(&self, wo: Vector3, _uc: f32, _u: Vector2) -> Option<BsdfSample> {
+        let wi = Vector3::new(-wo.x, -wo.y, wo.z);
+        if wi.z == 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            wi: wi,
+            f: self.reflectance / abs(wi.z),
+            pdf: 1.0,
+            is_specular: true,
+        })
+    }
+
+    fn pdf(&self, _wo: Vector3, _wi: Vector3) -> f32 {
+        0.0
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+}
+
+// Complex indices of refraction, eta and k, of common metals at the red,
+// green and blue wavelengths.
+pub const GOLD: (Color, Color) = (Color::new(0.143, 0.374, 1.442), Color::new(3.983, 2.385, 1.603));
+pub const COPPER: (Color, Color) = (Color::new(0.200, 0.924, 1.102), Color::new(3.912, 2.452, 2.142));
+pub const ALUMINIUM: (Color, Color) = (Color::new(1.657, 0.880, 0.521), Color::new(9.224, 6.270, 4.837));
+
+// Metal, described by its complex index of refraction eta + ik per channel.
+#[derive(Clone)]
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    roughness: f32,
+    distribution: Ggx,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f32) -> Conductor {
+        Conductor {
+            eta: eta,
+            k: k,
+            roughness: roughness,
+            distribution: Ggx::new(roughness),
+        }
+    }
+
+    pub fn gold(roughness: f32) -> Conductor {
+        Conductor::new(GOLD.0, GOLD.1, roughness)
+    }
+
+    pub fn copper(roughness: f32) -> Conductor {
+        Conductor::new(COPPER.0, COPPER.1, roughness)
+    }
+
+    pub fn aluminium(roughness: f32) -> Conductor {
+        Conductor::new(ALUMINIUM.0, ALUMINIUM.1, roughness)
+    }
+
+    fn is_smooth(&self) -> bool {
+        self.roughness < SMOOTH_ROUGHNESS
+    }
+}
+
+impl Bsdf for Conductor {
+    fn eval(&self, wo: Vector3, wi: Vector3) -> Color {
+        if self.is_smooth() || !same_hemisphere(wo, wi) {
+            return Color::BLACK;
+        }
+
+        let cos_o = abs(wo.z);
+        let cos_i = abs(wi.z);
+        if cos_o == 0.0 || cos_i == 0.0 {
+            return Color::BLACK;
+        }
+
+        let wm = wo + wi;
+        if length_squared(wm) == 0.0 {
+            return Color::BLACK;
+        }
+        let wm = normalize(wm);
+
+        let fresnel = fresnel_conductor(abs(dot(wo, wm)), self.eta, self.k);
+        let d = self.distribution.d(wm);
+        let g = self.distribution.g(wo, wi);
+        return fresnel * (d * g / (4.0 * cos_o * cos_i));
+    }
+
+    fn sample(&self, wo: Vector3, _uc: f32, u: Vector2) -> Option<BsdfSample> {
+        if self.is_smooth() {
+            let wi = Vector3::new(-wo.x, -wo.y, wo.z);
+            if wi.z == 0.0 {
+                return None;
+            }
+
+            return Some(BsdfSample {
+                wi: wi,
+                f: fresnel_conductor(abs(wi.z), self.eta, self.k) / abs(wi.z),
+                pdf: 1.0,
+                is_specular: true,
+            });
+        }
+
+        if wo.z == 0.0 {
+            return None;
+        }
+
+        let wm = self.distribution.sample_wm(wo, u);
+        let wi = reflect(wo, wm);
+        if !same_hemisphere(wo, wi) {
+            return None;
+        }
+
+        let pdf = self.pdf(wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            wi: wi,
+            f: self.eval(wo, wi),
+            pdf: pdf,
+            is_specular: false,
+        })
+    }
+
+    fn pdf(&self, wo: Vector3, wi: Vector3) -> f32 {
+        if self.is_smooth() || !same_hemisphere(wo, wi) {
+            return 0.0;
+        }
+
+        let wm = wo + wi;
+        if length_squared(wm) == 0.0 {
+            return 0.0;
+        }
+        let mut wm = normalize(wm);
+        if wm.z < 0.0 {
+            wm = -wm;
+        }
+
+        return self.distribution.d_visible(wo, wm) / (4.0 * abs(dot(wo, wm)));
+    }
+
+    fn is_specular(&self) -> bool {
+        self.is_smooth()
+    }
+}
+
+// Glass like interface, smooth or rough, reflecting and refracting light in
+// proportions given by the Fresnel equations. `ior` is the index of
+// refraction on the side opposite to the normal.
+#[derive(Clone)]
+pub struct Dielectric {
+    ior: f32,
+    roughness: f32,
+    distribution: Ggx,
+}
+
+impl Dielectric {
+    pub fn new(ior: f32, roughness: f32) -> Dielectric {
+        Dielectric {
+            ior: ior,
+            roughness: roughness,
+            distribution: Ggx::new(roughness),
+        }
+    }
+
+    fn is_smooth(&self) -> bool {
+        self.roughness < SMOOTH_ROUGHNESS || self.ior == 1.0
+    }
+
+    // Microfacet normal turning `wo` into `wi`, facing +z, along with the
+    // relative index of refraction crossed. None for backfacing microfacets.
+    fn half_vector(&self, wo: Vector3, wi: Vector3) -> Option<(Vector3, f32)> {
+        let cos_o = wo.z;
+        let cos_i = wi.z;
+        if cos_o == 0.0 || cos_i == 0.0 {
+            return None;
+        }
+
+        let reflection = cos_o * cos_i > 0.0;
+        let etap = if reflection { 1.0 } else if cos_o > 0.0 { self.ior } else { 1.0 / self.ior };
+
+        let wm = wi * etap + wo;
+        if length_squared(wm) == 0.0 {
+            return None;
+        }
+
+        let mut wm = normalize(wm);
+        if wm.z < 0.0 {
+            wm = -wm;
+        }
+
+        if dot(wm, wi) * cos_i < 0.0 || dot(wm, wo) * cos_o < 0.0 {
+            return None;
+        }
+
+        return Some((wm, etap));
+    }
+}
+
+impl Bsdf for Dielectric {
+    fn eval(&self, wo: Vector3, wi: Vector3) -> Color {
+        if self.is_smooth() {
+            return Color::BLACK;
+        }
+
+        let (wm, etap) = match self.half_vector(wo, wi) {
+            Some(result) => result,
+            None => return Color::BLACK,
+        };
+
+        let fresnel = fresnel_dielectric(dot(wo, wm), self.ior);
+        let d = self.distribution.d(wm);
+        let g = self.distribution.g(wo, wi);
+
+        if same_hemisphere(wo, wi) {
+            return Color::grey(d * g * fresnel / abs(4.0 * wi.z * wo.z));
+        }
+
+        let denom = square(dot(wi, wm) + dot(wo, wm) / etap) * wi.z * wo.z;
+        let transmitted = d * (1.0 - fresnel) * g * abs(dot(wi, wm) * dot(wo, wm) / denom);
+
+        // Radiance gets concentrated when entering a denser medium.
+        return Color::grey(transmitted / square(etap));
+    }
+
+    fn sample(&self, wo: Vector3, uc: f32, u: Vector2) -> Option<BsdfSample> {
+        if self.is_smooth() {
+            let reflectance = fresnel_dielectric(wo.z, self.ior);
+            let transmittance = 1.0 - reflectance;
+
+            if uc < reflectance {
+                let wi = Vector3::new(-wo.x, -wo.y, wo.z);
+                if wi.z == 0.0 {
+                    return None;
+                }
+
+                return Some(BsdfSample {
+                    wi: wi,
+                    f: Color::grey(reflectance / abs(wi.z)),
+                    pdf: reflectance,
+                    is_specular: true,
+                });
+            }
+
+            let (wi, etap) = refract(wo, Vector3::unit_z(), self.ior)?;
+            if wi.z == 0.0 {
+                return None;
+            }
+
+            return Some(BsdfSample {
+                wi: wi,
+                f: Color::grey(transmittance / (abs(wi.z) * square(etap))),
+                pdf: transmittance,
+                is_specular: true,
+            });
+        }
+
+        if wo.z == 0.0 {
+            return None;
+        }
+
+        let wm = self.distribution.sample_wm(wo, u);
+        let reflectance = fresnel_dielectric(dot(wo, wm), self.ior);
+
+        let wi = if uc < reflectance {
+            let wi = reflect(wo, wm);
+            if !same_hemisphere(wo, wi) {
+                return None;
+            }
+            wi
+        } else {
+            let (wi, _) = refract(wo, wm, self.ior)?;
+            if same_hemisphere(wo, wi) || wi.z == 0.0 {
+                return None;
+            }
+            wi
+        };
+
+        let pdf = self.pdf(wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            wi: wi,
+            f: self.eval(wo, wi),
+            pdf: pdf,
+            is_specular: false,
+        })
+    }
+
+    fn pdf(&self, wo: Vector3, wi: Vector3) -> f32 {
+        if self.is_smooth() {
+            return 0.0;
+        }
+
+        let (wm, etap) = match self.half_vector(wo, wi) {
+            Some(result) => result,
+            None => return 0.0,
+        };
+
+        let reflectance = fresnel_dielectric(dot(wo, wm), self.ior);
+        let d_visible = self.distribution.d_visible(wo, wm);
+
+        if same_hemisphere(wo, wi) {
+            return d_visible / (4.0 * abs(dot(wo, wm))) * reflectance;
+        }
+
+        let denom = square(dot(wi, wm) + dot(wo, wm) / etap);
+        let dwm_dwi = abs(dot(wi, wm)) / denom;
+        return d_visible * dwm_dwi * (1.0 - reflectance);
+    }
+
+    fn is_specular(&self) -> bool {
+        self.is_smooth()
+    }
+}
@@ -0,0 +1,234 @@
+use math::*;
+use raytracer::world::*;
+
+// Bounding volume hierarchy over an arbitrary list of primitives, only their
+// bounding boxes are needed to build it. Primitives are referred to by their
+// index in the list given to `Bvh::new`.
+//
+// The tree is built top-down using the surface area heuristic, then flattened
+// in depth-first order: the first child of an interior node directly follows
+// it in `nodes`, only the offset of the second child is stored.
+#[derive(Clone)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>,
+}
+
+#[derive(Clone, Copy)]
+struct BvhNode {
+    bounds: Aabb,
+    // Index of the first primitive in `indices` for leaves, index of the second
+    // child for interior nodes.
+    offset: usize,
+    primitive_count: u16,
+    axis: u8,
+}
+
+struct BuildPrimitive {
+    index: usize,
+    bounds: Aabb,
+    centroid: Vector3,
+}
+
+const BUCKET_COUNT: usize = 12;
+const MAX_PRIMITIVES_IN_LEAF: usize = 4;
+const TRAVERSAL_COST: f32 = 0.125;
+
+impl Bvh {
+    pub fn new(bounds: &[Aabb]) -> Bvh {
+        let mut primitives: Vec<BuildPrimitive> = bounds.iter().enumerate()
+            .map(|(i, &b)| BuildPrimitive { index: i, bounds: b, centroid: b.centroid() })
+            .collect();
+
+        let mut result = Bvh {
+            nodes: Vec::with_capacity(2 * bounds.len()),
+            indices: Vec::with_capacity(bounds.len()),
+        };
+
+        if !primitives.is_empty() {
+            result.build(&mut primitives);
+        }
+
+        return result;
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        match self.nodes.first() {
+            Some(node) => node.bounds,
+            None => Aabb::empty(),
+        }
+    }
+
+    fn build(&mut self, primitives: &mut [BuildPrimitive]) -> usize {
+        let node_index = self.nodes.len();
+
+        let bounds = primitives.iter().fold(Aabb::empty(), |b, p| b.union(p.bounds));
+        let centroid_bounds = primitives.iter().fold(Aabb::empty(), |b, p| b.extend(p.centroid));
+        let axis = centroid_bounds.maximum_extent();
+
+        self.nodes.push(BvhNode { bounds: bounds, offset: 0, primitive_count: 0, axis: axis as u8 });
+
+        let extent = centroid_bounds.max[axis] - centroid_bounds.min[axis];
+        let split = if primitives.len() <= MAX_PRIMITIVES_IN_LEAF || extent <= 0.0 {
+            None
+        } else {
+            find_sah_split(primitives, bounds, centroid_bounds, axis)
+        };
+
+        // Leaves store their primitive count on 16 bits.
+        let split = match split {
+            None if primitives.len() > u16::MAX as usize => Some(primitives.len() / 2),
+            split => split,
+        };
+
+        match split {
+            Some(mid) => {
+                let (left, right) = primitives.split_at_mut(mid);
+                self.build(left);
+                let second_child = self.build(right);
+                self.nodes[node_index].offset = second_child;
+            },
+            None => {
+                self.nodes[node_index].offset = self.indices.len();
+                self.nodes[node_index].primitive_count = primitives.len() as u16;
+                self.indices.extend(primitives.iter().map(|p| p.index));
+            }
+        }
+
+        return node_index;
+    }
+
+    // Returns the closest intersection found by `intersect_primitive` among the
+    // primitives whose bounds are hit by `ray`.
+    pub fn intersect<F>(&self, ray: Ray, mut intersect_primitive: F) -> Intersection
+        where F: FnMut(usize) -> Intersection
+    {
+        let mut result = Intersection::new();
+        if self.nodes.is_empty() {
+            return result;
+        }
+
+        let inv_direction = 1.0 / ray.direction;
+        let direction_is_negative = [ray.direction.x < 0.0, ray.direction.y < 0.0, ray.direction.z < 0.0];
+
+        // Skewed inputs can build trees of any depth, nodes past the fixed
+        // stack go to `overflow`, which only allocates when used.
+        let mut stack = [0usize; 64];
+        let mut stack_size = 0;
+        let mut overflow: Vec<usize> = Vec::new();
+        let mut node_index = 0;
+
+        loop {
+            let node = &self.nodes[node_index];
+            if node.bounds.intersect(ray, inv_direction, result.t).is_some() {
+                if node.primitive_count > 0 {
+                    let begin = node.offset;
+                    let end = begin + node.primitive_count as usize;
+                    for &primitive in self.indices[begin..end].iter() {
+                        let intersection = intersect_primitive(primitive);
+                        if intersection.is_valid && intersection.t < result.t {
+                            result = intersection;
+                        }
+                    }
+                } else {
+                    // Visit the child closest to the ray origin first, so that
+                    // the farther one is more likely to be culled.
+                    let farther = if direction_is_negative[node.axis as usize] {
+                        let farther = node_index + 1;
+                        node_index = node.offset;
+                        farther
+                    } else {
+                        node_index += 1;
+                        node.offset
+                    };
+                    if stack_size < stack.len() {
+                        stack[stack_size] = farther;
+                        stack_size += 1;
+                    } else {
+                        overflow.push(farther);
+                    }
+                    continue;
+                }
+            }
+
+            if let Some(next) = overflow.pop() {
+                node_index = next;
+                continue;
+            }
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            node_index = stack[stack_size];
+        }
+
+        return result;
+    }
+}
+
+// Buckets the primitives along `axis` and picks the boundary with the lowest
+// surface area heuristic cost. Returns None when a leaf is cheaper, otherwise
+// partitions `primitives` and returns the index of the first one on the right.
+fn find_sah_split(primitives: &mut [BuildPrimitive], bounds: Aabb,
+                  centroid_bounds: Aabb, axis: usize) -> Option<usize> {
+    let axis_min = centroid_bounds.min[axis];
+    let axis_extent = centroid_bounds.max[axis] - axis_min;
+    let bucket_of = |p: &BuildPrimitive| {
+        let b = (BUCKET_COUNT as f32 * (p.centroid[axis] - axis_min) / axis_extent) as usize;
+        if b >= BUCKET_COUNT { BUCKET_COUNT - 1 } else { b }
+    };
+
+    let mut counts = [0usize; BUCKET_COUNT];
+    let mut bucket_bounds = [Aabb::empty(); BUCKET_COUNT];
+    for p in primitives.iter() {
+        let b = bucket_of(p);
+        counts[b] += 1;
+        bucket_bounds[b] = bucket_bounds[b].union(p.bounds);
+    }
+
+    let inv_area = 1.0 / bounds.surface_area();
+    let mut best_cost = f32::MAX;
+    let mut best_split = 0;
+    for split in 0..BUCKET_COUNT - 1 {
+        let mut left = Aabb::empty();
+        let mut right = Aabb::empty();
+        let mut left_count = 0;
+        let mut right_count = 0;
+
+        for b in 0..split + 1 {
+            left = left.union(bucket_bounds[b]);
+            left_count += counts[b];
+        }
+        for b in split + 1..BUCKET_COUNT {
+            right = right.union(bucket_bounds[b]);
+            right_count += counts[b];
+        }
+
+        let cost = TRAVERSAL_COST + (left_count as f32 * left.surface_area() +
+                                     right_count as f32 * right.surface_area()) * inv_area;
+        if cost < best_cost {
+            best_cost = cost;
+            best_split = split;
+        }
+    }
+
+    let leaf_cost = primitives.len() as f32;
+    if best_cost >= leaf_cost {
+        return None;
+    }
+
+    // Partition in place, primitives in buckets up to `best_split` go left.
+    let mut mid = 0;
+    for i in 0..primitives.len() {
+        if bucket_of(&primitives[i]) <= best_split {
+            primitives.swap(i, mid);
+            mid += 1;
+        }
+    }
+
+    if mid == 0 || mid == primitives.len() {
+        mid = primitives.len() / 2;
+    }
+
+    return Some(mid);
+}
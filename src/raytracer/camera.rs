@@ -1,5 +1,64 @@
+use std::f32;
+
 use math::*;
 
+// How image positions map to rays.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Projection {
+    // Rays from the eye through the film.
+    Perspective,
+    // Parallel rays along the view direction, the image covering `height`
+    // units from top to bottom.
+    Orthographic { height: f32 },
+    // Equidistant fisheye: the angle from the view direction grows linearly
+    // with the distance from the image center, up to `fov` degrees across
+    // the circle fitting the image. Nothing is seen outside of it.
+    Fisheye { fov: f32 },
+    // Latitude-longitude panorama all around the camera, longitude across
+    // the image and latitude from the top down.
+    Equirectangular,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+// How the two eyes of a stereo camera agree on what they look at.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Convergence {
+    // Both eyes look straight ahead, nothing is seen at the same place in
+    // both images.
+    Parallel,
+    // Both eyes turn towards the convergence point, which distorts the
+    // images away from their center.
+    ToeIn,
+    // Both eyes look straight ahead with their film shifted so that the
+    // images match on the convergence plane.
+    OffAxis,
+}
+
+// Where the two eyes go in the rendered image.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StereoLayout {
+    SideBySide,
+    TopBottom,
+    // Rendered side by side, written as one file per eye.
+    Separate,
+}
+
+// Two eyes `interocular` apart on either side of the camera, converging
+// `convergence_distance` ahead of it. The image has room for both eyes,
+// left one first.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Stereo {
+    pub interocular: f32,
+    pub convergence: Convergence,
+    pub convergence_distance: f32,
+    pub layout: StereoLayout,
+}
+
 #[derive(Clone, Copy)]
 struct Film {
     distance: f32,
@@ -8,6 +67,16 @@ struct Film {
     center: Vector3,
 }
 
+// Omni-directional stereo eye of an equirectangular camera: every column of
+// the panorama is seen from `offset` to the right of the camera, across the
+// direction of that column, so that the eyes stay apart all around.
+#[derive(Clone, Copy)]
+struct PanoramaEye {
+    offset: f32,
+    // Distance at which the rays of both eyes meet, None for parallel ones.
+    convergence_distance: Option<f32>,
+}
+
 #[derive(Clone, Copy)]
 pub struct Camera {
     position: Vector3,
@@ -16,6 +85,19 @@ pub struct Camera {
     z_axis: Vector3,
 
     film: Film,
+    projection: Projection,
+    stereo: Option<Stereo>,
+    panorama_eye: Option<PanoramaEye>,
+
+    // Thin lens, a zero radius gives a pinhole camera where everything is
+    // sharp. Otherwise only the plane `focus_distance` away is.
+    aperture_radius: f32,
+    focus_distance: f32,
+
+    // Rays are traced at times spread uniformly over the interval the
+    // shutter stays open, blurring what moves meanwhile.
+    shutter_open: f32,
+    shutter_close: f32,
 }
 
 impl Camera {
@@ -34,10 +116,14 @@ impl Camera {
             fh = 1.0;
         }
 
+        Camera::from_film(fw * 0.5, fh * 0.5, film_distance)
+    }
+
+    fn from_film(half_width: f32, half_height: f32, distance: f32) -> Camera {
         let film = Film {
-            distance: film_distance,
-            half_width: fw * 0.5,
-            half_height: fh * 0.5,
+            distance: distance,
+            half_width: half_width,
+            half_height: half_height,
             center: Vector3::new(0.0, 0.0, 0.0),
         };
 
@@ -47,23 +133,318 @@ impl Camera {
             y_axis: Vector3::new(0.0, 0.0, 0.0),
             z_axis: Vector3::new(0.0, 0.0, 0.0),
             film: film,
+            projection: Projection::Perspective,
+            stereo: None,
+            panorama_eye: None,
+            aperture_radius: 0.0,
+            focus_distance: 1.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }
     }
 
+    // Points the camera at `target` with +z up.
     pub fn look_at(&mut self, position: Vector3, target: Vector3) {
+        self.look_at_up(position, target, Vector3::unit_z());
+    }
+
+    // `up` is the direction shown towards the top of the image. When looking
+    // along it, any direction orthogonal to the view is used instead.
+    pub fn look_at_up(&mut self, position: Vector3, target: Vector3, up: Vector3) {
         self.position = position;
         self.z_axis = safe_normalize(self.position - target);
-        self.x_axis = safe_normalize(cross(Vector3::unit_z(), self.z_axis));
+        if self.z_axis == Vector3::zero() {
+            self.z_axis = Vector3::unit_y();
+        }
+
+        let mut x_axis = safe_normalize(cross(up, self.z_axis));
+        if x_axis == Vector3::zero() {
+            x_axis = coordinate_system(self.z_axis).0;
+        }
+        self.x_axis = x_axis;
         self.y_axis = safe_normalize(cross(self.x_axis, self.z_axis));
 
         self.film.center = self.position - self.film.distance * self.z_axis;
     }
 
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray {
+    // Turns the camera counterclockwise around the view direction by `angle`
+    // degrees, the image appearing turned clockwise.
+    pub fn roll(&mut self, angle: f32) {
+        let (s, c) = angle.to_radians().sin_cos();
+        let x_axis = c * self.x_axis - s * self.y_axis;
+        let y_axis = c * self.y_axis + s * self.x_axis;
+        self.x_axis = x_axis;
+        self.y_axis = y_axis;
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    pub fn set_stereo(&mut self, stereo: Option<Stereo>) {
+        self.stereo = stereo;
+    }
+
+    pub fn stereo(&self) -> Option<Stereo> {
+        self.stereo
+    }
+
+    // The camera of one eye, the camera itself when it is not a stereo one.
+    // Stereo works with the perspective and equirectangular projections
+    // only. Panoramas make both toe-in and off-axis rays meet at the
+    // convergence distance in every direction.
+    pub fn eye(&self, eye: Eye) -> Camera {
+        let mut result = *self;
+        result.stereo = None;
+
+        let stereo = match self.stereo {
+            Some(stereo) => stereo,
+            None => return result,
+        };
+
+        let side = if eye == Eye::Left { -0.5 } else { 0.5 } * stereo.interocular;
+        if self.projection == Projection::Equirectangular {
+            result.panorama_eye = Some(PanoramaEye {
+                offset: side,
+                convergence_distance: match stereo.convergence {
+                    Convergence::Parallel => None,
+                    _ => Some(stereo.convergence_distance),
+                },
+            });
+            return result;
+        }
+
+        let offset = side * self.x_axis;
+        result.position = self.position + offset;
+        result.film.center = self.film.center + offset;
+
+        match stereo.convergence {
+            Convergence::Parallel => {},
+            Convergence::ToeIn => {
+                let target = self.position - stereo.convergence_distance * self.z_axis;
+                result.look_at_up(result.position, target, -self.y_axis);
+            },
+            Convergence::OffAxis => {
+                let shift = side * self.film.distance / stereo.convergence_distance;
+                result.film.center -= shift * self.x_axis;
+            },
+        }
+
+        return result;
+    }
+
+    pub fn set_aperture(&mut self, radius: f32) {
+        self.aperture_radius = radius;
+    }
+
+    // Distance along the view direction of the plane in focus.
+    pub fn set_focus_distance(&mut self, distance: f32) {
+        self.focus_distance = distance;
+    }
+
+    // Focuses on `point`, call after `look_at`.
+    pub fn focus_on(&mut self, point: Vector3) {
+        self.focus_distance = max(dot(self.position - point, self.z_axis), 1e-3);
+    }
+
+    pub fn set_shutter(&mut self, open: f32, close: f32) {
+        self.shutter_open = open;
+        self.shutter_close = close;
+    }
+
+    // `u` and `v` go from -1 to 1 left to right and top to bottom. `lens` is
+    // uniformly distributed in [0, 1)^2 and picks the point of the lens the
+    // ray goes through, only perspective cameras have one. `time` is
+    // uniformly distributed in [0, 1) and picks when the ray is traced
+    // while the shutter is open. Returns `None` where the projection sees
+    // nothing.
+    pub fn get_ray(&self, u: f32, v: f32, lens: Vector2, time: f32) -> Option<Ray> {
+        let time = self.shutter_open + time * (self.shutter_close - self.shutter_open);
+        let ray = match self.projection {
+            Projection::Perspective => Some(self.perspective_ray(u, v, lens)),
+            Projection::Orthographic { height } => {
+                let half_height = 0.5 * height;
+                let half_width = half_height * self.film.half_width / self.film.half_height;
+                let origin = self.position + u * half_width * self.x_axis + v * half_height * self.y_axis;
+                Some(Ray::new(origin, -self.z_axis))
+            },
+            Projection::Fisheye { fov } => {
+                // Distances are relative to the shortest side of the image.
+                let half_size = min(self.film.half_width, self.film.half_height);
+                let x = u * self.film.half_width / half_size;
+                let y = v * self.film.half_height / half_size;
+                let r = sqrt(x * x + y * y);
+                if r > 1.0 {
+                    return None;
+                }
+
+                let theta = r * 0.5 * fov.to_radians();
+                let (sin_theta, cos_theta) = theta.sin_cos();
+                let side = if r > 0.0 { (x * self.x_axis + y * self.y_axis) / r } else { Vector3::zero() };
+                Some(Ray::new(self.position, sin_theta * side - cos_theta * self.z_axis))
+            },
+            Projection::Equirectangular => {
+                let longitude = u * f32::consts::PI;
+                let latitude = v * 0.5 * f32::consts::PI;
+                let (sin_phi, cos_phi) = longitude.sin_cos();
+                let (sin_theta, cos_theta) = latitude.sin_cos();
+                let horizontal = sin_phi * self.x_axis - cos_phi * self.z_axis;
+                let direction = cos_theta * horizontal + sin_theta * self.y_axis;
+                match self.panorama_eye {
+                    Some(eye) => {
+                        let right = cos_phi * self.x_axis + sin_phi * self.z_axis;
+                        let origin = self.position + eye.offset * right;
+                        let direction = match eye.convergence_distance {
+                            Some(distance) => self.position + distance * direction - origin,
+                            None => direction,
+                        };
+                        Some(Ray::new(origin, direction))
+                    },
+                    None => Some(Ray::new(self.position, direction)),
+                }
+            },
+        };
+
+        ray.map(|ray| ray.with_time(time))
+    }
+
+    fn perspective_ray(&self, u: f32, v: f32, lens: Vector2) -> Ray {
         let x_axis = u * self.film.half_width * self.x_axis;
         let y_axis = v * self.film.half_height * self.y_axis;
         let film_position = self.film.center + x_axis + y_axis;
+        let direction = film_position - self.position;
+
+        if self.aperture_radius <= 0.0 {
+            return Ray::new(self.position, direction);
+        }
+
+        // Every ray through the lens from the same film position converges
+        // on the plane of focus.
+        let focus = self.position + direction * (self.focus_distance / self.film.distance);
+        let p = self.aperture_radius * concentric_sample_disk(lens);
+        let origin = self.position + p.x * self.x_axis + p.y * self.y_axis;
+
+        Ray::new(origin, focus - origin)
+    }
+}
+
+// Field of view of a `CameraBuilder`.
+#[derive(Clone, Copy, Debug)]
+enum FieldOfView {
+    // Angle in degrees between the top and bottom of the image.
+    Vertical(f32),
+    // Angle in degrees between the left and right of the image.
+    Horizontal(f32),
+    // Distance between the eye and a film whose longest side is one unit
+    // long, as with `Camera::new`.
+    FilmDistance(f32),
+}
+
+// Sets a `Camera` up from where it is, where it looks, which way is up and
+// how wide it sees, e.g.
+// `CameraBuilder::new(w, h).look_at(p, t).with_vertical_fov(40.0).build()`.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraBuilder {
+    image_width: u32,
+    image_height: u32,
+    position: Vector3,
+    target: Vector3,
+    up: Vector3,
+    roll: f32,
+    field_of_view: FieldOfView,
+    projection: Projection,
+    stereo: Option<Stereo>,
+    shutter: (f32, f32),
+}
+
+impl CameraBuilder {
+    // A camera at the origin looking down +y with +z up.
+    pub fn new(image_width: u32, image_height: u32) -> CameraBuilder {
+        CameraBuilder {
+            image_width: image_width,
+            image_height: image_height,
+            position: Vector3::zero(),
+            target: Vector3::unit_y(),
+            up: Vector3::unit_z(),
+            roll: 0.0,
+            field_of_view: FieldOfView::FilmDistance(1.0),
+            projection: Projection::Perspective,
+            stereo: None,
+            shutter: (0.0, 0.0),
+        }
+    }
+
+    pub fn look_at(mut self, position: Vector3, target: Vector3) -> CameraBuilder {
+        self.position = position;
+        self.target = target;
+        self
+    }
+
+    pub fn with_up(mut self, up: Vector3) -> CameraBuilder {
+        self.up = up;
+        self
+    }
+
+    // See `Camera::roll`.
+    pub fn with_roll(mut self, angle: f32) -> CameraBuilder {
+        self.roll = angle;
+        self
+    }
+
+    pub fn with_vertical_fov(mut self, degrees: f32) -> CameraBuilder {
+        self.field_of_view = FieldOfView::Vertical(degrees);
+        self
+    }
+
+    pub fn with_horizontal_fov(mut self, degrees: f32) -> CameraBuilder {
+        self.field_of_view = FieldOfView::Horizontal(degrees);
+        self
+    }
+
+    pub fn with_film_distance(mut self, distance: f32) -> CameraBuilder {
+        self.field_of_view = FieldOfView::FilmDistance(distance);
+        self
+    }
 
-        Ray::new(self.position, film_position - self.position)
+    // The field of view only applies to the perspective projection.
+    pub fn with_projection(mut self, projection: Projection) -> CameraBuilder {
+        self.projection = projection;
+        self
     }
-}
\ No newline at end of file
+
+    // The image size given to `new` is the size of each eye.
+    pub fn with_stereo(mut self, stereo: Stereo) -> CameraBuilder {
+        self.stereo = Some(stereo);
+        self
+    }
+
+    pub fn with_shutter(mut self, open: f32, close: f32) -> CameraBuilder {
+        self.shutter = (open, close);
+        self
+    }
+
+    pub fn build(&self) -> Camera {
+        let aspect = self.image_width as f32 / self.image_height as f32;
+
+        let mut result = match self.field_of_view {
+            FieldOfView::Vertical(degrees) => {
+                let half_height = tan(0.5 * degrees.to_radians());
+                Camera::from_film(half_height * aspect, half_height, 1.0)
+            },
+            FieldOfView::Horizontal(degrees) => {
+                let half_width = tan(0.5 * degrees.to_radians());
+                Camera::from_film(half_width, half_width / aspect, 1.0)
+            },
+            FieldOfView::FilmDistance(distance) => Camera::new(self.image_width, self.image_height, distance),
+        };
+
+        result.look_at_up(self.position, self.target, self.up);
+        result.roll(self.roll);
+        result.set_projection(self.projection);
+        result.set_stereo(self.stereo);
+        result.set_shutter(self.shutter.0, self.shutter.1);
+        result.focus_on(self.target);
+
+        return result;
+    }
+}
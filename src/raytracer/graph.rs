@@ -0,0 +1,147 @@
+use math::*;
+use raytracer::world::{Material, Object};
+
+// Named group of objects and child nodes placed together. Transforms apply
+// to everything below the node, after the children's own. Objects without a
+// material of their own take the one of the closest node with one. Hidden
+// nodes leave their whole subtree out.
+#[derive(Clone)]
+pub struct SceneNode {
+    name: String,
+    transform: Option<Transform>,
+    material: Option<Material>,
+    visible: bool,
+    objects: Vec<Object>,
+    children: Vec<SceneNode>,
+}
+
+impl SceneNode {
+    pub fn new(name: &str) -> SceneNode {
+        SceneNode {
+            name: name.to_string(),
+            transform: None,
+            material: None,
+            visible: true,
+            objects: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    pub fn with_transform(mut self, transform: Transform) -> SceneNode {
+        self.transform = Some(transform);
+        self
+    }
+
+    // Material of the objects below the node which don't have their own,
+    // unless overridden again further down.
+    pub fn with_material(mut self, material: Material) -> SceneNode {
+        self.material = Some(material);
+        self
+    }
+
+    pub fn with_visibility(mut self, visible: bool) -> SceneNode {
+        self.visible = visible;
+        self
+    }
+
+    pub fn add_object(&mut self, object: Object) {
+        self.objects.push(object);
+    }
+
+    pub fn add_child(&mut self, child: SceneNode) {
+        self.children.push(child);
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn transform(&self) -> Option<Transform> {
+        self.transform
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn objects(&self) -> &[Object] {
+        &self.objects
+    }
+
+    pub fn children(&self) -> &[SceneNode] {
+        &self.children
+    }
+
+    // First node called `name` in this subtree, depth first.
+    pub fn find(&self, name: &str) -> Option<&SceneNode> {
+        if self.name == name {
+            return Some(self);
+        }
+
+        self.children.iter().filter_map(|child| child.find(name)).next()
+    }
+
+    pub fn find_mut(&mut self, name: &str) -> Option<&mut SceneNode> {
+        if self.name == name {
+            return Some(self);
+        }
+
+        self.children.iter_mut().filter_map(|child| child.find_mut(name)).next()
+    }
+
+    // Object-to-world transform of the first node called `name`, None if
+    // there is no such node.
+    pub fn world_transform(&self, name: &str) -> Option<Transform> {
+        if self.name == name {
+            return Some(self.transform.unwrap_or_else(Transform::identity));
+        }
+
+        let child_transform = self.children.iter().filter_map(|child| child.world_transform(name)).next()?;
+        Some(match self.transform {
+            Some(transform) => child_transform.then(transform),
+            None => child_transform,
+        })
+    }
+
+    // Every visible object of the subtree, placed in the world and with
+    // overridden materials applied.
+    pub fn flatten(&self) -> Vec<Object> {
+        let mut result = Vec::new();
+        self.flatten_into(None, None, &mut result);
+        return result;
+    }
+
+    fn flatten_into(&self, parent_transform: Option<Transform>, parent_material: Option<&Material>,
+                    result: &mut Vec<Object>) {
+        if !self.visible {
+            return;
+        }
+
+        let transform = match (self.transform, parent_transform) {
+            (Some(own), Some(parent)) => Some(own.then(parent)),
+            (own, parent) => own.or(parent),
+        };
+        let material = self.material.as_ref().or(parent_material);
+
+        for object in self.objects.iter() {
+            let mut object = object.clone();
+            match material {
+                Some(material) if !object.has_own_material() => {
+                    object = object.with_default_material(material.clone());
+                },
+                _ => {},
+            }
+            if let Some(transform) = transform {
+                object = object.with_transform(transform);
+            }
+            result.push(object);
+        }
+
+        for child in self.children.iter() {
+            child.flatten_into(transform, material, result);
+        }
+    }
+}
+
+unsafe impl Send for SceneNode {}
+unsafe impl Sync for SceneNode {}
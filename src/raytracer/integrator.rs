@@ -0,0 +1,292 @@
+use math::*;
+use raytracer::world::*;
+use raytracer::sampler::Sampler;
+
+// Computes the radiance arriving along camera rays, implement it to plug a
+// new rendering algorithm into `raytrace`.
+pub trait Integrator: Send + Sync {
+    // Radiance coming back along `ray` from `scene`.
+    fn li(&self, ray: Ray, scene: &World, sampler: &mut Sampler) -> Color;
+
+    fn box_clone(&self) -> Box<Integrator>;
+}
+
+impl Clone for Box<Integrator> {
+    fn clone(&self) -> Box<Integrator> {
+        self.box_clone()
+    }
+}
+
+// Direct lighting only, from every light, seen directly or through mirrors
+// and glass.
+#[derive(Clone)]
+pub struct DirectLightingIntegrator;
+
+impl DirectLightingIntegrator {
+    pub fn new() -> DirectLightingIntegrator {
+        DirectLightingIntegrator
+    }
+}
+
+impl Default for DirectLightingIntegrator {
+    fn default() -> DirectLightingIntegrator {
+        DirectLightingIntegrator::new()
+    }
+}
+
+// Unidirectional path tracing with next-event estimation, paths are cut after
+// `max_depth` bounces.
+#[derive(Clone)]
+pub struct PathIntegrator {
+    max_depth: u32,
+}
+
+impl PathIntegrator {
+    pub fn new(max_depth: u32) -> PathIntegrator {
+        PathIntegrator {
+            max_depth: max_depth,
+        }
+    }
+}
+
+// Bounces after which paths may be terminated by Russian roulette.
+const RUSSIAN_ROULETTE_DEPTH: u32 = 3;
+
+// Mirror and glass bounces followed by `DirectLightingIntegrator`.
+const MAX_SPECULAR_DEPTH: u32 = 8;
+
+// Fraction of the light left after travelling `distance` through a medium of
+// absorption coefficient `absorption` (Beer-Lambert law).
+fn transmittance(absorption: Color, distance: f32) -> Color {
+    if absorption.is_black() {
+        return Color::WHITE;
+    }
+
+    (absorption * -distance).exp()
+}
+
+// Absorption coefficient of the medium a ray scattered from `wo` to `wi` by
+// `material` travels through, `absorption` being the one `wo` is in. Light
+// refracted below the surface enters the object, above it leaves it.
+fn next_medium(absorption: Color, material: &Material, wo: Vector3, wi: Vector3) -> Color {
+    if wo.z * wi.z > 0.0 {
+        return absorption;
+    }
+
+    if wi.z < 0.0 { material.absorption() } else { Color::BLACK }
+}
+
+// Radiance emitted by the surface hit by `ray`, emissive materials only emit
+// on the side their normal points to.
+fn emitted(intersection: &Intersection, material: &Material, ray: Ray) -> Color {
+    if intersection.is_entering(ray) { material.emission(intersection) } else { Color::BLACK }
+}
+
+impl DirectLightingIntegrator {
+    // `absorption` is the absorption coefficient of the medium `ray` travels
+    // through.
+    fn trace(&self, ray: Ray, scene: &World, sampler: &mut Sampler, depth: u32,
+             absorption: Color) -> Color {
+        let intersection = scene.intersect(ray);
+        let light_hit = scene.intersect_lights(ray).filter(|&(t, _, _)| t < intersection.t);
+
+        let distance = light_hit.map_or(intersection.t, |(t, _, _)| t);
+        let attenuation = transmittance(absorption, distance);
+
+        if let Some((_, radiance, _)) = light_hit {
+            return attenuation * radiance;
+        }
+
+        let material = match intersection.material {
+            Some(ref material) if intersection.is_valid => material,
+            _ => return attenuation * scene.background(ray),
+        };
+
+        let frame = Frame::from_normal(material.shading_normal(&intersection));
+        let wo = frame.to_local(-ray.direction);
+        let bsdf = material.bsdf(&intersection);
+
+        let mut result = emitted(&intersection, material, ray);
+
+        // Lights can't be seen through mirrors and glass, follow the
+        // reflected or refracted ray instead.
+        if bsdf.is_specular() {
+            if depth < MAX_SPECULAR_DEPTH {
+                if let Some(sample) = bsdf.sample(wo, sampler.get_1d(), sampler.get_2d()) {
+                    let next = intersection.spawn_ray(frame.to_world(sample.wi));
+                    let medium = next_medium(absorption, material, wo, sample.wi);
+                    result += sample.weight() * self.trace(next, scene, sampler, depth + 1, medium);
+                }
+            }
+
+            return attenuation * result;
+        }
+
+
+        for light in scene.lights().iter() {
+            let sample_count = light.sample_count();
+            let mut radiance = Color::BLACK;
+
+            for _ in 0..sample_count {
+                let sample = light.sample(intersection.position, sampler.get_2d(), intersection.time);
+                if sample.irradiance.is_black() {
+                    continue;
+                }
+
+                let wi = frame.to_local(sample.direction);
+                let f = bsdf.eval(wo, wi);
+                if f.is_black() {
+                    continue;
+                }
+
+                if !scene.is_occluded(intersection.position, sample.direction, sample.distance, intersection.time) {
+                    radiance += f * sample.irradiance * abs(wi.z);
+                }
+            }
+
+            result += radiance / sample_count as f32;
+        }
+
+        return attenuation * result;
+    }
+}
+
+impl Integrator for DirectLightingIntegrator {
+    fn li(&self, ray: Ray, scene: &World, sampler: &mut Sampler) -> Color {
+        self.trace(ray, scene, sampler, 0, Color::BLACK)
+    }
+
+    fn box_clone(&self) -> Box<Integrator> {
+        Box::new((*self).clone())
+    }
+}
+
+impl Integrator for PathIntegrator {
+    // Follows a path scattering off surfaces. At every vertex the lights are
+    // sampled directly and the next direction is drawn from the BSDF, an
+    // emitter reached either way is weighted with the power heuristic so that
+    // small lights and shiny surfaces both converge quickly.
+    fn li(&self, ray: Ray, scene: &World, sampler: &mut Sampler) -> Color {
+        let mut result = Color::BLACK;
+        let mut throughput = Color::WHITE;
+        let mut ray = ray;
+
+        // Density of the BSDF sample which produced `ray`, zero for the camera
+        // ray and specular bounces since lights seen through them can't be
+        // reached by light sampling.
+        let mut bsdf_pdf = 0.0;
+
+        // Absorption coefficient of the medium `ray` travels through.
+        let mut absorption = Color::BLACK;
+
+        let mut depth = 0;
+        loop {
+            let intersection = scene.intersect(ray);
+            let light_hit = scene.intersect_lights(ray).filter(|&(t, _, _)| t < intersection.t);
+
+            let distance = light_hit.map_or(intersection.t, |(t, _, _)| t);
+            throughput = throughput * transmittance(absorption, distance);
+
+            if let Some((_, radiance, light)) = light_hit {
+                let weight = if bsdf_pdf > 0.0 {
+                    power_heuristic(bsdf_pdf, light.pdf(ray.origin, ray.direction, ray.time))
+                } else {
+                    1.0
+                };
+                result += throughput * radiance * weight;
+                break;
+            }
+
+            let material = match intersection.material {
+                Some(ref material) if intersection.is_valid => material,
+                _ => {
+                    for light in scene.lights().iter() {
+                        let radiance = light.le(ray);
+                        if radiance.is_black() {
+                            continue;
+                        }
+
+                        let weight = if bsdf_pdf > 0.0 {
+                            power_heuristic(bsdf_pdf, light.pdf(ray.origin, ray.direction, ray.time))
+                        } else {
+                            1.0
+                        };
+                        result += throughput * radiance * weight;
+                    }
+                    result += throughput * scene.constant_background();
+                    break;
+                }
+            };
+
+            let frame = Frame::from_normal(material.shading_normal(&intersection));
+            let wo = frame.to_local(-ray.direction);
+            let bsdf = material.bsdf(&intersection);
+
+            let emission = emitted(&intersection, material, ray);
+            if !emission.is_black() {
+                let weight = match intersection.light {
+                    Some(light) if bsdf_pdf > 0.0 => {
+                        power_heuristic(bsdf_pdf, scene.lights()[light].pdf(ray.origin, ray.direction, ray.time))
+                    },
+                    _ => 1.0,
+                };
+                result += throughput * emission * weight;
+            }
+
+            if !bsdf.is_specular() {
+                for light in scene.lights().iter() {
+                    let sample = light.sample(intersection.position, sampler.get_2d(), intersection.time);
+                    if sample.irradiance.is_black() {
+                        continue;
+                    }
+
+                    let wi = frame.to_local(sample.direction);
+                    let f = bsdf.eval(wo, wi);
+                    if f.is_black() {
+                        continue;
+                    }
+
+                    if scene.is_occluded(intersection.position, sample.direction, sample.distance, intersection.time) {
+                        continue;
+                    }
+
+                    let weight = if sample.pdf > 0.0 {
+                        power_heuristic(sample.pdf, bsdf.pdf(wo, wi))
+                    } else {
+                        1.0
+                    };
+                    result += throughput * f * sample.irradiance * (abs(wi.z) * weight);
+                }
+            }
+
+            depth += 1;
+            if depth >= self.max_depth {
+                break;
+            }
+
+            let sample = match bsdf.sample(wo, sampler.get_1d(), sampler.get_2d()) {
+                Some(sample) => sample,
+                None => break,
+            };
+            throughput = throughput * sample.weight();
+            bsdf_pdf = if sample.is_specular { 0.0 } else { sample.pdf };
+            absorption = next_medium(absorption, material, wo, sample.wi);
+
+            if depth >= RUSSIAN_ROULETTE_DEPTH {
+                let survival = min(throughput.max_component(), 0.95);
+                if survival <= 0.0 || sampler.get_1d() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+
+            ray = intersection.spawn_ray(frame.to_world(sample.wi));
+        }
+
+        return result;
+    }
+
+    fn box_clone(&self) -> Box<Integrator> {
+        Box::new((*self).clone())
+    }
+}
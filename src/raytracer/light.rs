@@ -0,0 +1,725 @@
+use std::f32;
+use std::sync::Arc;
+
+use math::*;
+use raytracer::texture::Texture;
+use raytracer::world::{Intersectable, Intersection, Motion, SurfaceSample, MIN_HIT_DISTANCE, TOLERANCE};
+use raytracer::world::{intersect_transformed, sample_transformed, sample_pdf_transformed, hemisphere_transformed};
+
+// Light arriving at a point from a light source.
+pub struct LightSample {
+    // Normalized direction from the lit point towards the light.
+    pub direction: Vector3,
+    // Distance to the light, used to test for occluders.
+    pub distance: f32,
+    // Irradiance on a surface perpendicular to `direction`.
+    pub irradiance: Color,
+    // Solid angle density with which `direction` was picked, zero for lights
+    // reduced to a point or a direction.
+    pub pdf: f32,
+}
+
+// Shadow rays per shading point for area lights and emissive objects unless
+// told otherwise, see `Light::sample_count`.
+pub const AREA_LIGHT_SAMPLES: u32 = 16;
+
+pub trait Light {
+    // `u` is a uniformly distributed random point in [0, 1)^2, only used by
+    // lights with an area. `time` places lights which move.
+    fn sample(&self, position: Vector3, u: Vector2, time: f32) -> LightSample;
+
+    // Number of samples to average, more samples give smoother penumbrae.
+    fn sample_count(&self) -> u32 { 1 }
+
+    // Distance and emitted radiance when a ray hits the light itself, lights
+    // without a surface can't be seen.
+    fn intersect(&self, _ray: Ray) -> Option<(f32, Color)> { None }
+
+    // Solid angle density with which `sample` picks `direction` from
+    // `position` at `time`, zero when it never does.
+    fn pdf(&self, _position: Vector3, _direction: Vector3, _time: f32) -> f32 { 0.0 }
+
+    // Radiance arriving along `ray` when it escapes the scene, only lights
+    // infinitely far away have some.
+    fn le(&self, _ray: Ray) -> Color { Color::BLACK }
+
+    // Whether the light surrounds the scene, replacing the constant
+    // background of `World`.
+    fn is_environment(&self) -> bool { false }
+
+    fn box_clone(&self) -> Box<Light>;
+}
+
+impl Clone for Box<Light> {
+    fn clone(&self) -> Box<Light> {
+        self.box_clone()
+    }
+}
+
+#[derive(Clone)]
+pub struct PointLight {
+    position: Vector3,
+    intensity: Color,
+}
+
+impl PointLight {
+    pub fn new(position: Vector3, color: Color, intensity: f32) -> PointLight {
+        PointLight {
+            position: position,
+            intensity: color * intensity,
+        }
+    }
+}
+
+// Light coming from infinitely far away in a single direction, like the sun.
+#[derive(Clone)]
+pub struct DirectionalLight {
+    direction: Vector3,
+    irradiance: Color,
+}
+
+impl DirectionalLight {
+    // `direction` is the direction the light travels in.
+    pub fn new(direction: Vector3, color: Color, intensity: f32) -> DirectionalLight {
+        DirectionalLight {
+            direction: safe_normalize(direction),
+            irradiance: color * intensity,
+        }
+    }
+}
+
+// Point light restricted to a cone. The intensity is constant up to
+// `falloff_start` degrees from the axis, then smoothly goes down to zero at
+// `cone_angle` degrees.
+#[derive(Clone)]
+pub struct SpotLight {
+    position: Vector3,
+    direction: Vector3,
+    intensity: Color,
+    cos_cone_angle: f32,
+    cos_falloff_start: f32,
+}
+
+impl SpotLight {
+    pub fn new(position: Vector3, direction: Vector3, color: Color, intensity: f32,
+               cone_angle: f32, falloff_start: f32) -> SpotLight {
+        SpotLight {
+            position: position,
+            direction: safe_normalize(direction),
+            intensity: color * intensity,
+            cos_cone_angle: cos(cone_angle.to_radians()),
+            cos_falloff_start: cos(min(falloff_start, cone_angle).to_radians()),
+        }
+    }
+
+    fn falloff(&self, cos_theta: f32) -> f32 {
+        if cos_theta < self.cos_cone_angle {
+            return 0.0;
+        }
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+
+        let delta = (cos_theta - self.cos_cone_angle) / (self.cos_falloff_start - self.cos_cone_angle);
+        let result = square(square(delta));
+        return result;
+    }
+}
+
+// Rectangle spanned by `edge_u` and `edge_v` from `corner`, emitting on the
+// side its normal, cross(edge_u, edge_v), points to.
+#[derive(Clone)]
+pub struct RectLight {
+    corner: Vector3,
+    edge_u: Vector3,
+    edge_v: Vector3,
+    normal: Vector3,
+    area: f32,
+    radiance: Color,
+    samples: u32,
+}
+
+impl RectLight {
+    pub fn new(corner: Vector3, edge_u: Vector3, edge_v: Vector3, color: Color, intensity: f32,
+               samples: u32) -> RectLight {
+        let n = cross(edge_u, edge_v);
+        RectLight {
+            corner: corner,
+            edge_u: edge_u,
+            edge_v: edge_v,
+            normal: safe_normalize(n),
+            area: length(n),
+            radiance: color * intensity,
+            samples: samples,
+        }
+    }
+}
+
+// Disk emitting on the side its normal points to.
+#[derive(Clone)]
+pub struct DiskLight {
+    center: Vector3,
+    normal: Vector3,
+    tangent: Vector3,
+    bitangent: Vector3,
+    radius: f32,
+    radiance: Color,
+    samples: u32,
+}
+
+impl DiskLight {
+    pub fn new(center: Vector3, normal: Vector3, radius: f32, color: Color, intensity: f32,
+               samples: u32) -> DiskLight {
+        let normal = safe_normalize(normal);
+        let (tangent, bitangent) = coordinate_system(normal);
+        DiskLight {
+            center: center,
+            normal: normal,
+            tangent: tangent,
+            bitangent: bitangent,
+            radius: radius,
+            radiance: color * intensity,
+            samples: samples,
+        }
+    }
+
+    fn area(&self) -> f32 {
+        f32::consts::PI * square(self.radius)
+    }
+}
+
+#[derive(Clone)]
+pub struct SphereLight {
+    center: Vector3,
+    radius: f32,
+    radiance: Color,
+    samples: u32,
+}
+
+impl SphereLight {
+    pub fn new(center: Vector3, radius: f32, color: Color, intensity: f32, samples: u32) -> SphereLight {
+        SphereLight {
+            center: center,
+            radius: radius,
+            radiance: color * intensity,
+            samples: samples,
+        }
+    }
+
+    // Solid angle of the cone under which the sphere is seen from `position`,
+    // along with its axis and the cosine of its half angle. None from inside.
+    fn cone(&self, position: Vector3) -> Option<(f32, Vector3, f32)> {
+        let to_center = self.center - position;
+        let distance_squared = length_squared(to_center);
+        if distance_squared <= square(self.radius) {
+            return None;
+        }
+
+        let sin_theta_max_squared = square(self.radius) / distance_squared;
+        let cos_theta_max = sqrt(max(0.0, 1.0 - sin_theta_max_squared));
+        let solid_angle = 2.0 * f32::consts::PI * (1.0 - cos_theta_max);
+
+        Some((solid_angle, to_center / sqrt(distance_squared), cos_theta_max))
+    }
+}
+
+// Light emitted by an object with an emissive material, on the side its
+// normal points to. Points are picked as the geometry samples them, planes
+// are sampled by direction instead. Rays hit the object itself rather than
+// the light, which has no `intersect`.
+#[derive(Clone)]
+pub struct GeometryLight {
+    geometry: Box<Intersectable>,
+    // Motion of the object, the geometry being in its object space.
+    motion: Option<Motion>,
+    radiance: Arc<Texture>,
+    samples: u32,
+}
+
+impl GeometryLight {
+    pub fn new(geometry: Box<Intersectable>, radiance: Arc<Texture>, samples: u32) -> GeometryLight {
+        GeometryLight {
+            geometry: geometry,
+            motion: None,
+            radiance: radiance,
+            samples: samples,
+        }
+    }
+
+    // Moves the light with the object it belongs to.
+    pub fn with_motion(mut self, motion: Motion) -> GeometryLight {
+        self.motion = Some(motion);
+        self
+    }
+
+    fn intersect_geometry(&self, ray: Ray, transform: Option<&Transform>) -> Intersection {
+        match transform {
+            Some(transform) => intersect_transformed(&*self.geometry, transform, ray),
+            None => self.geometry.intersect(ray),
+        }
+    }
+
+    fn sample_geometry(&self, u: Vector2, transform: Option<&Transform>) -> Option<SurfaceSample> {
+        match transform {
+            Some(transform) => sample_transformed(&*self.geometry, transform, u),
+            None => self.geometry.sample(u),
+        }
+    }
+
+    fn sample_pdf_geometry(&self, hit: &Intersection, transform: Option<&Transform>) -> f32 {
+        match transform {
+            Some(transform) => sample_pdf_transformed(&*self.geometry, transform, hit),
+            None => self.geometry.sample_pdf(hit),
+        }
+    }
+
+    fn hemisphere_geometry(&self, position: Vector3, transform: Option<&Transform>) -> Option<Vector3> {
+        match transform {
+            Some(transform) => hemisphere_transformed(&*self.geometry, transform, position),
+            None => self.geometry.hemisphere(position),
+        }
+    }
+
+    // Direction with a cosine-weighted density around `axis`, the surface
+    // covering the whole hemisphere.
+    fn sample_hemisphere(&self, position: Vector3, axis: Vector3, u: Vector2,
+                         transform: Option<&Transform>) -> LightSample {
+        let local = cosine_sample_hemisphere(u);
+        let direction = Frame::from_normal(axis).to_world(local);
+        let pdf = local.z / f32::consts::PI;
+
+        let ray = Ray::new(position, direction);
+        let mut hit = self.intersect_geometry(ray, transform);
+        if !hit.is_valid || pdf <= 0.0 || dot(hit.normal, direction) >= 0.0 {
+            return LightSample {
+                direction: direction,
+                distance: 0.0,
+                irradiance: Color::BLACK,
+                pdf: 0.0,
+            };
+        }
+
+        hit.position = ray_point(ray, hit.t);
+        LightSample {
+            direction: direction,
+            distance: hit.t,
+            irradiance: self.radiance.color(hit.uv, hit.position) * (1.0 / pdf),
+            pdf: pdf,
+        }
+    }
+}
+
+// Light coming from every direction, read from an equirectangular map whose
+// top row is straight up (+z). Directions are picked in proportion to the
+// brightness of the map so that small bright areas like the sun are found.
+#[derive(Clone)]
+pub struct EnvironmentLight {
+    map: Arc<EnvironmentMap>,
+    // Rotation around the vertical axis, in radians.
+    rotation: f32,
+    scale: Color,
+    samples: u32,
+}
+
+struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentLight {
+    // `pixels` are the radiance of the map, row after row from the top.
+    // `rotation` is in degrees, counterclockwise seen from above.
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>, color: Color, intensity: f32,
+               rotation: f32, samples: u32) -> EnvironmentLight {
+        assert_eq!(pixels.len(), width * height);
+
+        // Rows near the poles cover a smaller solid angle.
+        let mut func = Vec::with_capacity(pixels.len());
+        for y in 0..height {
+            let sin_theta = sin(f32::consts::PI * (y as f32 + 0.5) / height as f32);
+            for x in 0..width {
+                func.push(pixels[y * width + x].luminance() * sin_theta);
+            }
+        }
+
+        EnvironmentLight {
+            map: Arc::new(EnvironmentMap {
+                width: width,
+                height: height,
+                distribution: Distribution2D::new(&func, width, height),
+                pixels: pixels,
+            }),
+            rotation: rotation.to_radians(),
+            scale: color * intensity,
+            samples: samples,
+        }
+    }
+
+    // The same radiance from every direction.
+    pub fn uniform(color: Color, intensity: f32) -> EnvironmentLight {
+        EnvironmentLight::new(1, 1, vec![Color::WHITE], color, intensity, 0.0, 1)
+    }
+
+    // Position in the map of `direction`, along with the sine of its angle
+    // to the vertical.
+    fn map_position(&self, direction: Vector3) -> (Vector2, f32) {
+        let d = safe_normalize(direction);
+        let theta = acos(clamp(d.z, -1.0, 1.0));
+        let phi = atan2(d.y, d.x) - self.rotation;
+
+        let u = phi / (2.0 * f32::consts::PI);
+        let u = u - floor(u);
+        (Vector2::new(u, theta / f32::consts::PI), sin(theta))
+    }
+
+    fn map_direction(&self, uv: Vector2) -> Vector3 {
+        let theta = uv.y * f32::consts::PI;
+        let phi = uv.x * 2.0 * f32::consts::PI + self.rotation;
+        let sin_theta = sin(theta);
+        Vector3::new(sin_theta * cos(phi), sin_theta * sin(phi), cos(theta))
+    }
+
+    fn lookup(&self, uv: Vector2) -> Color {
+        let map = &*self.map;
+        let x = clamp(floor(uv.x * map.width as f32), 0.0, (map.width - 1) as f32) as usize;
+        let y = clamp(floor(uv.y * map.height as f32), 0.0, (map.height - 1) as f32) as usize;
+        map.pixels[y * map.width + x] * self.scale
+    }
+
+    // Solid angle density of the direction at `uv` in the map.
+    fn map_pdf(&self, uv: Vector2, sin_theta: f32) -> f32 {
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        self.map.distribution.pdf(uv) / (2.0 * square(f32::consts::PI) * sin_theta)
+    }
+}
+
+// Irradiance from a point of an area light picked with `density` per unit
+// area: L cos(theta_light) / (distance^2 density).
+fn area_sample(position: Vector3, light_point: Vector3, light_normal: Vector3, density: f32,
+               radiance: Color) -> LightSample {
+    let light_vec = light_point - position;
+    let distance_squared = length_squared(light_vec);
+    let distance = sqrt(distance_squared);
+    let direction = light_vec / distance;
+
+    let cos_light = dot(-direction, light_normal);
+    if cos_light <= 0.0 {
+        return LightSample {
+            direction: direction,
+            distance: distance,
+            irradiance: Color::BLACK,
+            pdf: 0.0,
+        };
+    }
+
+    LightSample {
+        direction: direction,
+        distance: distance,
+        irradiance: radiance * (cos_light / (distance_squared * density)),
+        pdf: distance_squared * density / cos_light,
+    }
+}
+
+// Solid angle density of `area_sample` for the point at distance `t` along
+// `direction`.
+fn area_pdf(direction: Vector3, t: f32, light_normal: Vector3, density: f32) -> f32 {
+    let cos_light = dot(-direction, light_normal);
+    if cos_light <= 0.0 {
+        return 0.0;
+    }
+
+    return square(t) * density / cos_light;
+}
+
+// Hit distance of a ray with the plane going through `point`, front side only.
+fn intersect_plane(ray: Ray, point: Vector3, normal: Vector3) -> Option<f32> {
+    let denom = dot(normal, ray.direction);
+    if abs(denom) <= TOLERANCE {
+        return None;
+    }
+
+    let t = dot(point - ray.origin, normal) / denom;
+    if t > MIN_HIT_DISTANCE { Some(t) } else { None }
+}
+
+impl Light for PointLight {
+    fn sample(&self, position: Vector3, _u: Vector2, _time: f32) -> LightSample {
+        let light_vec = self.position - position;
+        let distance_squared = length_squared(light_vec);
+        let distance = sqrt(distance_squared);
+
+        LightSample {
+            direction: light_vec / distance,
+            distance: distance,
+            irradiance: self.intensity / distance_squared,
+            pdf: 0.0,
+        }
+    }
+
+    fn box_clone(&self) -> Box<Light> {
+        Box::new((*self).clone())
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _position: Vector3, _u: Vector2, _time: f32) -> LightSample {
+        LightSample {
+            direction: -self.direction,
+            distance: f32::MAX,
+            irradiance: self.irradiance,
+            pdf: 0.0,
+        }
+    }
+
+    fn box_clone(&self) -> Box<Light> {
+        Box::new((*self).clone())
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, position: Vector3, _u: Vector2, _time: f32) -> LightSample {
+        let light_vec = self.position - position;
+        let distance_squared = length_squared(light_vec);
+        let distance = sqrt(distance_squared);
+        let direction = light_vec / distance;
+
+        let falloff = self.falloff(dot(-direction, self.direction));
+
+        LightSample {
+            direction: direction,
+            distance: distance,
+            irradiance: self.intensity * (falloff / distance_squared),
+            pdf: 0.0,
+        }
+    }
+
+    fn box_clone(&self) -> Box<Light> {
+        Box::new((*self).clone())
+    }
+}
+
+impl Light for RectLight {
+    fn sample(&self, position: Vector3, u: Vector2, _time: f32) -> LightSample {
+        let point = self.corner + u.x * self.edge_u + u.y * self.edge_v;
+        area_sample(position, point, self.normal, 1.0 / self.area, self.radiance)
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.samples
+    }
+
+    fn intersect(&self, ray: Ray) -> Option<(f32, Color)> {
+        let t = intersect_plane(ray, self.corner, self.normal)?;
+
+        let p = ray_point(ray, t) - self.corner;
+        let u = dot(p, self.edge_u) / length_squared(self.edge_u);
+        let v = dot(p, self.edge_v) / length_squared(self.edge_v);
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return None;
+        }
+
+        let radiance = if dot(ray.direction, self.normal) < 0.0 { self.radiance } else { Color::BLACK };
+        Some((t, radiance))
+    }
+
+    fn pdf(&self, position: Vector3, direction: Vector3, _time: f32) -> f32 {
+        match self.intersect(Ray::new(position, direction)) {
+            Some((t, _)) => area_pdf(direction, t, self.normal, 1.0 / self.area),
+            None => 0.0,
+        }
+    }
+
+    fn box_clone(&self) -> Box<Light> {
+        Box::new((*self).clone())
+    }
+}
+
+impl Light for DiskLight {
+    fn sample(&self, position: Vector3, u: Vector2, _time: f32) -> LightSample {
+        let r = self.radius * sqrt(u.x);
+        let phi = 2.0 * f32::consts::PI * u.y;
+        let point = self.center + r * cos(phi) * self.tangent + r * sin(phi) * self.bitangent;
+
+        area_sample(position, point, self.normal, 1.0 / self.area(), self.radiance)
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.samples
+    }
+
+    fn intersect(&self, ray: Ray) -> Option<(f32, Color)> {
+        let t = intersect_plane(ray, self.center, self.normal)?;
+        if length_squared(ray_point(ray, t) - self.center) > square(self.radius) {
+            return None;
+        }
+
+        let radiance = if dot(ray.direction, self.normal) < 0.0 { self.radiance } else { Color::BLACK };
+        Some((t, radiance))
+    }
+
+    fn pdf(&self, position: Vector3, direction: Vector3, _time: f32) -> f32 {
+        match self.intersect(Ray::new(position, direction)) {
+            Some((t, _)) => area_pdf(direction, t, self.normal, 1.0 / self.area()),
+            None => 0.0,
+        }
+    }
+
+    fn box_clone(&self) -> Box<Light> {
+        Box::new((*self).clone())
+    }
+}
+
+impl Light for SphereLight {
+    // Samples the cone of directions under which the sphere is seen from
+    // `position`, so that no sample is wasted on its hidden side.
+    fn sample(&self, position: Vector3, u: Vector2, _time: f32) -> LightSample {
+        let (solid_angle, w, cos_theta_max) = match self.cone(position) {
+            Some(cone) => cone,
+            None => return LightSample {
+                direction: Vector3::unit_z(),
+                distance: 0.0,
+                irradiance: Color::BLACK,
+                pdf: 0.0,
+            },
+        };
+
+        let to_center = self.center - position;
+        let (tangent, bitangent) = coordinate_system(w);
+
+        let cos_theta = 1.0 - u.x * (1.0 - cos_theta_max);
+        let sin_theta = sqrt(max(0.0, 1.0 - square(cos_theta)));
+        let phi = 2.0 * f32::consts::PI * u.y;
+
+        let direction = cos_theta * w + sin_theta * (cos(phi) * tangent + sin(phi) * bitangent);
+
+        // Distance to the near side of the sphere along the sampled direction.
+        let b = dot(direction, to_center);
+        let det = square(b) - (length_squared(to_center) - square(self.radius));
+        let distance = b - sqrt(max(0.0, det));
+
+        LightSample {
+            direction: direction,
+            distance: distance,
+            irradiance: self.radiance * solid_angle,
+            pdf: 1.0 / solid_angle,
+        }
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.samples
+    }
+
+    fn intersect(&self, ray: Ray) -> Option<(f32, Color)> {
+        let relative_origin = ray.origin - self.center;
+        let b = dot(ray.direction, relative_origin);
+        let c = length_squared(relative_origin) - square(self.radius);
+        let det = square(b) - c;
+        if det <= 0.0 {
+            return None;
+        }
+
+        let det_sqrt = sqrt(det);
+        let t = if -b - det_sqrt > MIN_HIT_DISTANCE { -b - det_sqrt } else { -b + det_sqrt };
+        if t > MIN_HIT_DISTANCE { Some((t, self.radiance)) } else { None }
+    }
+
+    fn pdf(&self, position: Vector3, direction: Vector3, _time: f32) -> f32 {
+        match self.cone(position) {
+            Some((solid_angle, w, cos_theta_max)) if dot(direction, w) >= cos_theta_max => 1.0 / solid_angle,
+            _ => 0.0,
+        }
+    }
+
+    fn box_clone(&self) -> Box<Light> {
+        Box::new((*self).clone())
+    }
+}
+
+impl Light for GeometryLight {
+    fn sample(&self, position: Vector3, u: Vector2, time: f32) -> LightSample {
+        let transform = self.motion.as_ref().map(|motion| motion.transform(time));
+        if let Some(axis) = self.hemisphere_geometry(position, transform.as_ref()) {
+            return self.sample_hemisphere(position, axis, u, transform.as_ref());
+        }
+
+        match self.sample_geometry(u, transform.as_ref()) {
+            Some(sample) => {
+                let radiance = self.radiance.color(sample.uv, sample.position);
+                area_sample(position, sample.position, sample.normal, sample.pdf, radiance)
+            },
+            None => LightSample {
+                direction: Vector3::unit_z(),
+                distance: 0.0,
+                irradiance: Color::BLACK,
+                pdf: 0.0,
+            },
+        }
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.samples
+    }
+
+    fn pdf(&self, position: Vector3, direction: Vector3, time: f32) -> f32 {
+        let transform = self.motion.as_ref().map(|motion| motion.transform(time));
+        if let Some(axis) = self.hemisphere_geometry(position, transform.as_ref()) {
+            return max(dot(safe_normalize(direction), axis), 0.0) / f32::consts::PI;
+        }
+
+        let ray = Ray::new(position, direction).with_time(time);
+        let mut hit = self.intersect_geometry(ray, transform.as_ref());
+        if !hit.is_valid {
+            return 0.0;
+        }
+
+        hit.position = ray_point(ray, hit.t);
+        hit.time = time;
+        area_pdf(direction, hit.t, safe_normalize(hit.normal), self.sample_pdf_geometry(&hit, transform.as_ref()))
+    }
+
+    fn box_clone(&self) -> Box<Light> {
+        Box::new((*self).clone())
+    }
+}
+
+impl Light for EnvironmentLight {
+    fn sample(&self, _position: Vector3, u: Vector2, _time: f32) -> LightSample {
+        let (uv, _) = self.map.distribution.sample(u);
+        let direction = self.map_direction(uv);
+        let pdf = self.map_pdf(uv, sin(uv.y * f32::consts::PI));
+
+        LightSample {
+            direction: direction,
+            distance: f32::MAX,
+            irradiance: if pdf > 0.0 { self.lookup(uv) / pdf } else { Color::BLACK },
+            pdf: pdf,
+        }
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.samples
+    }
+
+    fn pdf(&self, _position: Vector3, direction: Vector3, _time: f32) -> f32 {
+        let (uv, sin_theta) = self.map_position(direction);
+        self.map_pdf(uv, sin_theta)
+    }
+
+    fn le(&self, ray: Ray) -> Color {
+        let (uv, _) = self.map_position(ray.direction);
+        self.lookup(uv)
+    }
+
+    fn is_environment(&self) -> bool {
+        true
+    }
+
+    fn box_clone(&self) -> Box<Light> {
+        Box::new((*self).clone())
+    }
+}
@@ -0,0 +1,329 @@
+use std::sync::Arc;
+
+use math::*;
+use raytracer::world::*;
+use raytracer::bvh::Bvh;
+
+#[derive(Clone)]
+pub struct Triangle {
+    positions: [Vector3; 3],
+    normals: Option<[Vector3; 3]>,
+    uvs: Option<[Vector2; 3]>,
+}
+
+impl Triangle {
+    pub fn new(p0: Vector3, p1: Vector3, p2: Vector3) -> Triangle {
+        Triangle {
+            positions: [p0, p1, p2],
+            normals: None,
+            uvs: None,
+        }
+    }
+
+    pub fn smooth(positions: [Vector3; 3], normals: [Vector3; 3], uvs: [Vector2; 3]) -> Triangle {
+        Triangle {
+            positions: positions,
+            normals: Some(normals),
+            uvs: Some(uvs),
+        }
+    }
+}
+
+// Vertex attributes are stored per vertex and shared by every triangle
+// referencing them. `normals` and `uvs` are either empty or have the same
+// length as `positions`.
+pub struct MeshData {
+    positions: Vec<Vector3>,
+    normals: Vec<Vector3>,
+    uvs: Vec<Vector2>,
+    indices: Vec<u32>,
+    bvh: Bvh,
+    // Running sum of the triangle areas, to pick them proportionally to
+    // their area.
+    area_cdf: Vec<f32>,
+}
+
+#[derive(Clone)]
+pub struct TriangleMesh {
+    data: Arc<MeshData>,
+}
+
+impl TriangleMesh {
+    pub fn new(positions: Vec<Vector3>, normals: Vec<Vector3>, uvs: Vec<Vector2>,
+               indices: Vec<u32>) -> TriangleMesh {
+        assert!(indices.len().is_multiple_of(3));
+        assert!(normals.is_empty() || normals.len() == positions.len());
+        assert!(uvs.is_empty() || uvs.len() == positions.len());
+        assert!(indices.iter().all(|&i| (i as usize) < positions.len()));
+
+        let bounds: Vec<Aabb> = indices.chunks(3)
+            .map(|i| triangle_bounds([positions[i[0] as usize],
+                                      positions[i[1] as usize],
+                                      positions[i[2] as usize]]))
+            .collect();
+
+        let mut total_area = 0.0;
+        let area_cdf: Vec<f32> = indices.chunks(3)
+            .map(|i| {
+                total_area += triangle_area([positions[i[0] as usize],
+                                             positions[i[1] as usize],
+                                             positions[i[2] as usize]]);
+                total_area
+            })
+            .collect();
+
+        let data = MeshData {
+            positions: positions,
+            normals: normals,
+            uvs: uvs,
+            indices: indices,
+            bvh: Bvh::new(&bounds),
+            area_cdf: area_cdf,
+        };
+
+        TriangleMesh { data: Arc::new(data) }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.data.indices.len() / 3
+    }
+
+    fn vertex_indices(&self, triangle: usize) -> [usize; 3] {
+        let i = &self.data.indices[triangle * 3..triangle * 3 + 3];
+        [i[0] as usize, i[1] as usize, i[2] as usize]
+    }
+
+    fn positions(&self, triangle: usize) -> [Vector3; 3] {
+        let [i0, i1, i2] = self.vertex_indices(triangle);
+        let p = &self.data.positions;
+        [p[i0], p[i1], p[i2]]
+    }
+
+    fn uvs(&self, triangle: usize) -> Option<[Vector2; 3]> {
+        if self.data.uvs.is_empty() {
+            return None;
+        }
+
+        let [i0, i1, i2] = self.vertex_indices(triangle);
+        let uv = &self.data.uvs;
+        Some([uv[i0], uv[i1], uv[i2]])
+    }
+
+    fn intersect_triangle(&self, ray: Ray, triangle: usize) -> Intersection {
+        let mut result = Intersection::new();
+
+        let positions = self.positions(triangle);
+        if let Some((t, b)) = intersect_watertight(ray, positions) {
+            let [i0, i1, i2] = self.vertex_indices(triangle);
+
+            let normals = if self.data.normals.is_empty() {
+                None
+            } else {
+                let n = &self.data.normals;
+                Some([n[i0], n[i1], n[i2]])
+            };
+
+            fill_intersection(&mut result, t, b, positions, normals, self.uvs(triangle));
+        }
+
+        return result;
+    }
+}
+
+impl Intersectable for Triangle {
+    fn intersect(&self, ray: Ray) -> Intersection {
+        let mut result = Intersection::new();
+
+        if let Some((t, b)) = intersect_watertight(ray, self.positions) {
+            fill_intersection(&mut result, t, b, self.positions, self.normals, self.uvs);
+        }
+
+        return result;
+    }
+
+    fn bounds(&self) -> Aabb {
+        triangle_bounds(self.positions)
+    }
+
+    fn area(&self) -> f32 {
+        triangle_area(self.positions)
+    }
+
+    fn sample(&self, u: Vector2) -> Option<SurfaceSample> {
+        Some(sample_triangle(self.positions, self.uvs, u, 1.0 / self.area()))
+    }
+
+    fn box_clone(&self) -> Box<Intersectable> {
+        Box::new((*self).clone())
+    }
+}
+
+impl Intersectable for TriangleMesh {
+    fn intersect(&self, ray: Ray) -> Intersection {
+        self.data.bvh.intersect(ray, |triangle| self.intersect_triangle(ray, triangle))
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.data.bvh.bounds()
+    }
+
+    fn area(&self) -> f32 {
+        self.data.area_cdf.last().cloned().unwrap_or(0.0)
+    }
+
+    // Picks a triangle with `u.x` proportionally to its area, then reuses
+    // what is left of `u.x` to pick a point in it.
+    fn sample(&self, u: Vector2) -> Option<SurfaceSample> {
+        let cdf = &self.data.area_cdf;
+        let area = self.area();
+        if area <= 0.0 {
+            return None;
+        }
+
+        let target = u.x * area;
+        let triangle = ::std::cmp::min(cdf.partition_point(|&a| a <= target), cdf.len() - 1);
+
+        let begin = if triangle > 0 { cdf[triangle - 1] } else { 0.0 };
+        let triangle_area = cdf[triangle] - begin;
+        let remapped = if triangle_area > 0.0 { (target - begin) / triangle_area } else { 0.0 };
+
+        let u = Vector2::new(min(remapped, 0.99999994), u.y);
+        Some(sample_triangle(self.positions(triangle), self.uvs(triangle), u, 1.0 / area))
+    }
+
+    fn box_clone(&self) -> Box<Intersectable> {
+        Box::new((*self).clone())
+    }
+}
+
+fn triangle_bounds(p: [Vector3; 3]) -> Aabb {
+    Aabb::new(p[0], p[1]).extend(p[2])
+}
+
+fn triangle_area(p: [Vector3; 3]) -> f32 {
+    0.5 * length(cross(p[1] - p[0], p[2] - p[0]))
+}
+
+fn sample_triangle(p: [Vector3; 3], uv: Option<[Vector2; 3]>, u: Vector2, pdf: f32) -> SurfaceSample {
+    let b = uniform_sample_triangle(u);
+    let uv = triangle_uvs(uv);
+    SurfaceSample {
+        position: b[0] * p[0] + b[1] * p[1] + b[2] * p[2],
+        normal: safe_normalize(cross(p[1] - p[0], p[2] - p[0])),
+        uv: b[0] * uv[0] + b[1] * uv[1] + b[2] * uv[2],
+        pdf: pdf,
+    }
+}
+
+// Without texture coordinates, the barycentric coordinates themselves are
+// used as the surface parameterization.
+fn triangle_uvs(uv: Option<[Vector2; 3]>) -> [Vector2; 3] {
+    uv.unwrap_or([Vector2::zero(), Vector2::new(1.0, 0.0), Vector2::new(0.0, 1.0)])
+}
+
+fn fill_intersection(result: &mut Intersection, t: f32, b: [f32; 3],
+                     p: [Vector3; 3], n: Option<[Vector3; 3]>, uv: Option<[Vector2; 3]>) {
+    result.is_valid = true;
+    result.t = t;
+
+    result.normal = safe_normalize(cross(p[1] - p[0], p[2] - p[0]));
+    result.shading_normal = match n {
+        Some(n) => safe_normalize(b[0] * n[0] + b[1] * n[1] + b[2] * n[2]),
+        None => result.normal,
+    };
+
+    // The winding may disagree with the vertex normals, which are trusted.
+    if dot(result.normal, result.shading_normal) < 0.0 {
+        result.normal = -result.normal;
+    }
+
+    let uv = triangle_uvs(uv);
+    result.uv = b[0] * uv[0] + b[1] * uv[1] + b[2] * uv[2];
+
+    // Solves dp = dP/du du + dP/dv dv along two edges.
+    let (duv1, duv2) = (uv[1] - uv[0], uv[2] - uv[0]);
+    let (dp1, dp2) = (p[1] - p[0], p[2] - p[0]);
+    let determinant = duv1.x * duv2.y - duv1.y * duv2.x;
+    if abs(determinant) > 1e-12 {
+        let inv = 1.0 / determinant;
+        result.dpdu = (duv2.y * dp1 - duv1.y * dp2) * inv;
+        result.dpdv = (duv1.x * dp2 - duv2.x * dp1) * inv;
+    } else {
+        let (dpdu, dpdv) = coordinate_system(safe_normalize(cross(dp1, dp2)));
+        result.dpdu = dpdu;
+        result.dpdv = dpdv;
+    }
+}
+
+// Watertight ray/triangle intersection (Woop, Benthin & Wald, 2013).
+// The triangle is transformed into a space where the ray starts at the origin
+// and points down +z, so that the edge tests are consistent along shared
+// edges and never let a ray slip between two adjacent triangles.
+// Returns the hit distance along with the barycentric coordinates.
+fn intersect_watertight(ray: Ray, p: [Vector3; 3]) -> Option<(f32, [f32; 3])> {
+    let d = ray.direction;
+
+    let kz = max_dimension(Vector3::new(abs(d.x), abs(d.y), abs(d.z)));
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    if d[kz] < 0.0 {
+        ::std::mem::swap(&mut kx, &mut ky);
+    }
+
+    let sx = -d[kx] / d[kz];
+    let sy = -d[ky] / d[kz];
+    let sz = 1.0 / d[kz];
+
+    let a = p[0] - ray.origin;
+    let b = p[1] - ray.origin;
+    let c = p[2] - ray.origin;
+
+    let ax = a[kx] + sx * a[kz];
+    let ay = a[ky] + sy * a[kz];
+    let bx = b[kx] + sx * b[kz];
+    let by = b[ky] + sy * b[kz];
+    let cx = c[kx] + sx * c[kz];
+    let cy = c[ky] + sy * c[kz];
+
+    let mut u = cx * by - cy * bx;
+    let mut v = ax * cy - ay * cx;
+    let mut w = bx * ay - by * ax;
+
+    // Fall back to double precision when the ray passes exactly through an
+    // edge, so the sign test below stays consistent for both triangles.
+    if u == 0.0 || v == 0.0 || w == 0.0 {
+        u = (cx as f64 * by as f64 - cy as f64 * bx as f64) as f32;
+        v = (ax as f64 * cy as f64 - ay as f64 * cx as f64) as f32;
+        w = (bx as f64 * ay as f64 - by as f64 * ax as f64) as f32;
+    }
+
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+
+    let az = sz * a[kz];
+    let bz = sz * b[kz];
+    let cz = sz * c[kz];
+    let t_scaled = u * az + v * bz + w * cz;
+
+    let inv_det = 1.0 / det;
+    let t = t_scaled * inv_det;
+    if t <= MIN_HIT_DISTANCE {
+        return None;
+    }
+
+    return Some((t, [u * inv_det, v * inv_det, w * inv_det]));
+}
+
+fn max_dimension(v: Vector3) -> usize {
+    if v.x > v.y {
+        if v.x > v.z { 0 } else { 2 }
+    } else {
+        if v.y > v.z { 1 } else { 2 }
+    }
+}
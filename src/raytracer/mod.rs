@@ -4,6 +4,36 @@ pub use self::camera::*;
 pub mod world;
 pub use self::world::*;
 
+pub mod mesh;
+pub use self::mesh::*;
+
+pub mod bvh;
+pub use self::bvh::*;
+
+pub mod graph;
+pub use self::graph::*;
+
+pub mod light;
+pub use self::light::*;
+
+pub mod sky;
+pub use self::sky::*;
+
+pub mod texture;
+pub use self::texture::*;
+
+pub mod bsdf;
+pub use self::bsdf::*;
+
+pub mod sampler;
+pub use self::sampler::*;
+
+pub mod integrator;
+pub use self::integrator::*;
+
+pub mod render;
+pub use self::render::*;
+
 use math::*;
 
 pub fn make_plane(n: Vector3, d: f32, material: Material) -> Object {
@@ -16,4 +46,11 @@ pub fn make_sphere(p: Vector3, r: f32, material: Material) -> Object {
     let sphere = Sphere::new(p, r);
     let result = Object::new(Box::new(sphere), material);
     return result;
+}
+
+pub fn make_mesh(positions: Vec<Vector3>, normals: Vec<Vector3>, uvs: Vec<Vector2>,
+                 indices: Vec<u32>, material: Material) -> Object {
+    let mesh = TriangleMesh::new(positions, normals, uvs, indices);
+    let result = Object::new(Box::new(mesh), material);
+    return result;
 }
\ No newline at end of file
@@ -0,0 +1,136 @@
+use std::cmp;
+use std::thread;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+
+use math::*;
+use raytracer::camera::{Camera, Eye, StereoLayout};
+use raytracer::world::World;
+use raytracer::integrator::Integrator;
+use raytracer::sampler::Sampler;
+use utils::Image;
+
+pub struct RenderJob {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl RenderJob {
+    // Stops the render threads, they exit after their current row.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+// Renders `world` with `integrator` on background threads, sending each pixel
+// through `sender` as soon as it is done. The channel is closed once every
+// pixel has been sent or the returned job has been cancelled.
+pub fn raytrace(world: &World, integrator: &Integrator, image: &Image,
+                sender: Sender<(u32, u32, Color)>) -> RenderJob {
+
+    let job = RenderJob { cancelled: Arc::new(AtomicBool::new(false)) };
+
+    let width = image.width;
+    let height = image.height;
+    let x_slices = 2;
+    let y_slices = 2;
+
+    for i in 0..x_slices {
+        let x_beg = i * (width / x_slices);
+        let x_end = (i + 1) * (width / x_slices);
+
+        for j in 0..y_slices {
+            let y_beg = j * (height / y_slices);
+            let y_end = (j + 1) * (height / y_slices);
+
+            spawn_thread(world, integrator, &sender, &job, x_beg, x_end, y_beg, y_end, image);
+        }
+    }
+
+    return job;
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_thread(world: &World, integrator: &Integrator,
+                sender: &Sender<(u32, u32, Color)>, job: &RenderJob,
+                x_slice_begin: u32, x_slice_end: u32,
+                y_slice_begin: u32, y_slice_end: u32, image: &Image)
+{
+    let world = world.clone();
+    let integrator = integrator.box_clone();
+    let s = sender.clone();
+    let cancelled = job.cancelled.clone();
+
+    let width = image.width;
+    let height = image.height;
+    let samples = image.samples;
+
+    thread::spawn(move || {
+        raytrace_sub(&world, &*integrator, s, &cancelled, x_slice_begin, x_slice_end,
+                     y_slice_begin, y_slice_end,
+                     width, height, samples);
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn raytrace_sub(world: &World, integrator: &Integrator,
+                sender: Sender<(u32, u32, Color)>, cancelled: &AtomicBool,
+                x_slice_begin: u32, x_slice_end: u32,
+                y_slice_begin: u32, y_slice_end: u32,
+                width: u32, height: u32, samples: u32) {
+
+    let (cameras, view_width, view_height) = views(world.camera(), width, height);
+    let inv_view_width = 1.0 / view_width as f32;
+    let inv_view_height = 1.0 / view_height as f32;
+
+    let mut sampler = Sampler::new();
+
+    for y in y_slice_begin..y_slice_end {
+        if cancelled.load(Ordering::Relaxed) {
+            return;
+        }
+
+        for x in x_slice_begin..x_slice_end {
+            // An odd pixel out with odd sizes goes to the last camera.
+            let index = (x / view_width + y / view_height) as usize;
+            let camera = &cameras[cmp::min(index, cameras.len() - 1)];
+            let view_x = x % view_width;
+            let view_y = y % view_height;
+
+            let mut color = Color::BLACK;
+            for _s in 0..samples {
+                let jitter = sampler.get_2d();
+                let _x = view_x as f32 + jitter.x;
+                let _y = view_y as f32 + jitter.y;
+
+                let u = _x * inv_view_width * 2.0 - 1.0;
+                let v = _y * inv_view_height * 2.0 - 1.0;
+
+                if let Some(ray) = camera.get_ray(u, v, sampler.get_2d(), sampler.get_1d()) {
+                    color += integrator.li(ray, world, &mut sampler);
+                }
+            }
+
+            let final_color = color / samples as f32;
+            if sender.send((x, y, final_color)).is_err() {
+                // Nobody is listening anymore.
+                return;
+            }
+        }
+    }
+}
+
+// The cameras seeing the image and the size of the part each of them sees:
+// the whole image, or one half per eye for stereo cameras.
+fn views(camera: &Camera, width: u32, height: u32) -> (Vec<Camera>, u32, u32) {
+    let stereo = match camera.stereo() {
+        Some(stereo) => stereo,
+        None => return (vec![*camera], width, height),
+    };
+
+    let eyes = vec![camera.eye(Eye::Left), camera.eye(Eye::Right)];
+    match stereo.layout {
+        StereoLayout::SideBySide | StereoLayout::Separate => (eyes, cmp::max(width / 2, 1), height),
+        StereoLayout::TopBottom => (eyes, width, cmp::max(height / 2, 1)),
+    }
+}
@@ -0,0 +1,33 @@
+use math::*;
+
+extern crate rand;
+use self::rand::Rng;
+
+// Source of the random numbers used while rendering, one per render thread.
+pub struct Sampler {
+    rng: rand::ThreadRng,
+}
+
+impl Sampler {
+    pub fn new() -> Sampler {
+        Sampler {
+            rng: rand::thread_rng(),
+        }
+    }
+
+    // Uniformly distributed in [0, 1).
+    pub fn get_1d(&mut self) -> f32 {
+        self.rng.gen()
+    }
+
+    // Uniformly distributed in [0, 1)^2.
+    pub fn get_2d(&mut self) -> Vector2 {
+        Vector2::new(self.rng.gen(), self.rng.gen())
+    }
+}
+
+impl Default for Sampler {
+    fn default() -> Sampler {
+        Sampler::new()
+    }
+}
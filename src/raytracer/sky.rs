@@ -0,0 +1,191 @@
+use std::f32;
+
+use math::*;
+use raytracer::light::*;
+
+// Clear sky daylight after Preetham et al., "A Practical Analytic Model for
+// Daylight" (1999). The sky is black below the horizon, the sun itself is
+// left to the `DirectionalLight` returned by `sun`.
+#[derive(Clone)]
+pub struct SkyLight {
+    sun_direction: Vector3,
+    sun_theta: f32,
+    turbidity: f32,
+
+    // Perez coefficients of the luminance and chromaticity distributions.
+    perez_y: [f32; 5],
+    perez_cx: [f32; 5],
+    perez_cy: [f32; 5],
+
+    // Zenith luminance and chromaticity, the luminance being divided by the
+    // Perez distribution there.
+    zenith: (f32, f32, f32),
+    intensity: f32,
+
+    // Tabulated copy of the sky used to pick directions towards the
+    // brightest parts of it.
+    distribution: EnvironmentLight,
+}
+
+// Illuminance of the sun outside the atmosphere, in klx, to bring sky
+// luminances in kcd/m^2 to the unit of the sun's irradiance.
+const SOLAR_ILLUMINANCE: f32 = 128.0;
+
+// Resolution of the map driving sky sampling.
+const SKY_MAP_WIDTH: usize = 128;
+const SKY_MAP_HEIGHT: usize = 64;
+
+impl SkyLight {
+    // The sun is `elevation` degrees above the horizon and `azimuth` degrees
+    // counterclockwise from +x seen from above. `turbidity` measures the haze,
+    // from 2 for a very clear sky to 10 for a hazy one. `intensity` is the
+    // irradiance of the sun outside the atmosphere.
+    pub fn new(elevation: f32, azimuth: f32, turbidity: f32, intensity: f32, samples: u32) -> SkyLight {
+        let theta = (90.0 - elevation).to_radians();
+        let phi = azimuth.to_radians();
+        let t = turbidity;
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (f32::consts::PI - 2.0 * theta);
+        let zenith_y = (4.0453 * t - 4.9710) * tan(chi) - 0.2155 * t + 2.4192;
+
+        let theta2 = square(theta);
+        let theta3 = theta2 * theta;
+        let zenith_x =
+            square(t) * (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta) +
+            t * (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta + 0.00394) +
+            (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta + 0.25886);
+        let zenith_cy =
+            square(t) * (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta) +
+            t * (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta + 0.00516) +
+            (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta + 0.26688);
+
+        let perez_y = [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251,
+                       0.1206 * t - 2.5771, -0.0670 * t + 0.3703];
+        let perez_cx = [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125,
+                        -0.0641 * t - 0.8989, -0.0033 * t + 0.0452];
+        let perez_cy = [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102,
+                        -0.0441 * t - 1.6537, -0.0109 * t + 0.0529];
+
+        let zenith = (zenith_y / perez(&perez_y, 0.0, theta),
+                      zenith_x / perez(&perez_cx, 0.0, theta),
+                      zenith_cy / perez(&perez_cy, 0.0, theta));
+
+        let sin_theta = sin(theta);
+        let mut result = SkyLight {
+            sun_direction: Vector3::new(sin_theta * cos(phi), sin_theta * sin(phi), cos(theta)),
+            sun_theta: theta,
+            turbidity: turbidity,
+            perez_y: perez_y,
+            perez_cx: perez_cx,
+            perez_cy: perez_cy,
+            zenith: zenith,
+            intensity: intensity,
+            distribution: EnvironmentLight::uniform(Color::BLACK, 0.0),
+        };
+
+        let mut pixels = Vec::with_capacity(SKY_MAP_WIDTH * SKY_MAP_HEIGHT);
+        for y in 0..SKY_MAP_HEIGHT {
+            let theta = f32::consts::PI * (y as f32 + 0.5) / SKY_MAP_HEIGHT as f32;
+            for x in 0..SKY_MAP_WIDTH {
+                let phi = 2.0 * f32::consts::PI * (x as f32 + 0.5) / SKY_MAP_WIDTH as f32;
+                let direction = Vector3::new(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+                pixels.push(result.radiance(direction));
+            }
+        }
+        result.distribution = EnvironmentLight::new(SKY_MAP_WIDTH, SKY_MAP_HEIGHT, pixels, Color::WHITE,
+                                                    1.0, 0.0, samples);
+
+        return result;
+    }
+
+    // Direct sunlight matching the sky: the sun's irradiance once dimmed by
+    // the atmosphere it crosses.
+    pub fn sun(&self) -> DirectionalLight {
+        let color = sun_transmittance(self.sun_theta, self.turbidity);
+        DirectionalLight::new(-self.sun_direction, color, self.intensity)
+    }
+
+    fn radiance(&self, direction: Vector3) -> Color {
+        let d = safe_normalize(direction);
+        if d.z <= 0.0 {
+            return Color::BLACK;
+        }
+
+        let theta = acos(min(d.z, 1.0));
+        let gamma = acos(clamp(dot(d, self.sun_direction), -1.0, 1.0));
+
+        let luminance = self.zenith.0 * perez(&self.perez_y, theta, gamma);
+        let x = self.zenith.1 * perez(&self.perez_cx, theta, gamma);
+        let y = self.zenith.2 * perez(&self.perez_cy, theta, gamma);
+
+        xyy_to_rgb(x, y, max(luminance, 0.0)) * (self.intensity / SOLAR_ILLUMINANCE)
+    }
+}
+
+// Perez et al. distribution of the sky at `theta` from the zenith and `gamma`
+// from the sun.
+fn perez(c: &[f32; 5], theta: f32, gamma: f32) -> f32 {
+    let cos_theta = max(cos(theta), 1e-3);
+    (1.0 + c[0] * (c[1] / cos_theta).exp()) * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * square(cos(gamma)))
+}
+
+// CIE xyY to linear sRGB.
+fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> Color {
+    if y <= 0.0 {
+        return Color::BLACK;
+    }
+
+    let cx = x / y * luminance;
+    let cz = (1.0 - x - y) / y * luminance;
+    Color::new(max(0.0, 3.2406 * cx - 1.5372 * luminance - 0.4986 * cz),
+               max(0.0, -0.9689 * cx + 1.8758 * luminance + 0.0415 * cz),
+               max(0.0, 0.0557 * cx - 0.2040 * luminance + 1.0570 * cz))
+}
+
+// Fraction of sunlight going through the atmosphere when the sun is `theta`
+// from the zenith, from Rayleigh and aerosol scattering (Preetham et al.,
+// appendix), at the red, green and blue wavelengths.
+fn sun_transmittance(theta: f32, turbidity: f32) -> Color {
+    let air_mass = 1.0 / (cos(theta) + 0.15 * max(93.885 - theta.to_degrees(), 1e-3).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+
+    let transmittance = |wavelength: f32| {
+        let rayleigh = 0.008735 * wavelength.powf(-4.08);
+        let aerosol = beta * wavelength.powf(-1.3);
+        (-(rayleigh + aerosol) * air_mass).exp()
+    };
+
+    // Wavelengths in micrometers.
+    Color::new(transmittance(0.680), transmittance(0.550), transmittance(0.440))
+}
+
+impl Light for SkyLight {
+    fn sample(&self, position: Vector3, u: Vector2, time: f32) -> LightSample {
+        let mut result = self.distribution.sample(position, u, time);
+        if result.pdf > 0.0 {
+            result.irradiance = self.radiance(result.direction) / result.pdf;
+        }
+
+        return result;
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.distribution.sample_count()
+    }
+
+    fn pdf(&self, position: Vector3, direction: Vector3, time: f32) -> f32 {
+        self.distribution.pdf(position, direction, time)
+    }
+
+    fn le(&self, ray: Ray) -> Color {
+        self.radiance(ray.direction)
+    }
+
+    fn is_environment(&self) -> bool {
+        true
+    }
+
+    fn box_clone(&self) -> Box<Light> {
+        Box::new((*self).clone())
+    }
+}
@@ -0,0 +1,196 @@
+use std::sync::Arc;
+
+use math::*;
+
+// A value varying over surfaces, looked up at the texture coordinates and
+// position of the point hit.
+pub trait Texture: Send + Sync {
+    fn color(&self, uv: Vector2, position: Vector3) -> Color;
+
+    // Scalar parameters like roughness read the average of the channels.
+    fn value(&self, uv: Vector2, position: Vector3) -> f32 {
+        let c = self.color(uv, position);
+        (c.red + c.green + c.blue) / 3.0
+    }
+}
+
+#[derive(Clone)]
+pub struct ConstantTexture {
+    color: Color,
+}
+
+impl ConstantTexture {
+    pub fn new(color: Color) -> ConstantTexture {
+        ConstantTexture { color: color }
+    }
+}
+
+// How texture coordinates outside of [0, 1] are brought back into the image.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WrapMode {
+    Repeat,
+    Mirror,
+    Clamp,
+}
+
+// Image mapped onto [0, 1]^2 with bilinear filtering, v going up from the
+// bottom row.
+#[derive(Clone)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Arc<Vec<Color>>,
+    wrap: WrapMode,
+}
+
+impl ImageTexture {
+    // `pixels` are stored row after row from the top.
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>, wrap: WrapMode) -> ImageTexture {
+        assert!(width > 0 && height > 0 && pixels.len() == width * height);
+        ImageTexture {
+            width: width,
+            height: height,
+            pixels: Arc::new(pixels),
+            wrap: wrap,
+        }
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let x = wrap(x, self.width, self.wrap);
+        let y = wrap(y, self.height, self.wrap);
+        self.pixels[y * self.width + x]
+    }
+}
+
+fn wrap(i: i64, size: usize, mode: WrapMode) -> usize {
+    let size = size as i64;
+    let result = match mode {
+        WrapMode::Repeat => i.rem_euclid(size),
+        WrapMode::Mirror => {
+            let i = i.rem_euclid(2 * size);
+            if i < size { i } else { 2 * size - 1 - i }
+        },
+        WrapMode::Clamp => i.clamp(0, size - 1),
+    };
+    return result as usize;
+}
+
+// Alternates between two colors over a grid of `frequency` squares per unit
+// of texture coordinates.
+#[derive(Clone)]
+pub struct CheckerTexture {
+    even: Color,
+    odd: Color,
+    frequency: f32,
+}
+
+impl CheckerTexture {
+    pub fn new(even: Color, odd: Color, frequency: f32) -> CheckerTexture {
+        CheckerTexture {
+            even: even,
+            odd: odd,
+            frequency: frequency,
+        }
+    }
+}
+
+// Goes linearly from one color at `start` to another at `end`, constant
+// across the line between them.
+#[derive(Clone)]
+pub struct GradientTexture {
+    start: Vector3,
+    axis: Vector3,
+    from: Color,
+    to: Color,
+}
+
+impl GradientTexture {
+    pub fn new(start: Vector3, end: Vector3, from: Color, to: Color) -> GradientTexture {
+        let axis = end - start;
+        let length_squared = length_squared(axis);
+        GradientTexture {
+            start: start,
+            axis: if length_squared > 0.0 { axis / length_squared } else { Vector3::zero() },
+            from: from,
+            to: to,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NoiseKind {
+    // Fractal sum of `octaves` layers of Perlin noise.
+    Perlin { octaves: u32 },
+    // Distance to the closest cell center, for stone or scale patterns.
+    Worley,
+}
+
+// Blend of two colors driven by 3D noise at the position hit, `scale` being
+// the size of the noise features.
+#[derive(Clone)]
+pub struct NoiseTexture {
+    kind: NoiseKind,
+    scale: f32,
+    from: Color,
+    to: Color,
+}
+
+impl NoiseTexture {
+    pub fn new(kind: NoiseKind, scale: f32, from: Color, to: Color) -> NoiseTexture {
+        NoiseTexture {
+            kind: kind,
+            scale: scale,
+            from: from,
+            to: to,
+        }
+    }
+}
+
+fn mix(a: Color, b: Color, t: f32) -> Color {
+    a * (1.0 - t) + b * t
+}
+
+impl Texture for ConstantTexture {
+    fn color(&self, _uv: Vector2, _position: Vector3) -> Color {
+        self.color
+    }
+}
+
+impl Texture for ImageTexture {
+    fn color(&self, uv: Vector2, _position: Vector3) -> Color {
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = (1.0 - uv.y) * self.height as f32 - 0.5;
+        let (x0, y0) = (floor(x), floor(y));
+        let (dx, dy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        mix(mix(self.texel(x0, y0), self.texel(x0 + 1, y0), dx),
+            mix(self.texel(x0, y0 + 1), self.texel(x0 + 1, y0 + 1), dx),
+            dy)
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn color(&self, uv: Vector2, _position: Vector3) -> Color {
+        let square = floor(uv.x * self.frequency) + floor(uv.y * self.frequency);
+        if square.rem_euclid(2.0) == 0.0 { self.even } else { self.odd }
+    }
+}
+
+impl Texture for GradientTexture {
+    fn color(&self, _uv: Vector2, position: Vector3) -> Color {
+        mix(self.from, self.to, saturate(dot(position - self.start, self.axis)))
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn color(&self, _uv: Vector2, position: Vector3) -> Color {
+        let p = position / self.scale;
+        let t = match self.kind {
+            NoiseKind::Perlin { octaves } => 0.5 + 0.5 * fbm(p, octaves),
+            NoiseKind::Worley => worley(p),
+        };
+
+        mix(self.from, self.to, saturate(t))
+    }
+}
@@ -1,50 +1,154 @@
+use std::cmp::Ordering;
 use std::f32;
-use std::thread;
-use std::sync::mpsc::Sender;
+use std::ops;
+use std::sync::Arc;
 
 use math::*;
-use Camera;
-use utils::Image;
-
-extern crate rand;
-use self::rand::distributions::{IndependentSample, Range};
+use raytracer::bsdf::*;
+use raytracer::camera::Camera;
+use raytracer::bvh::Bvh;
+use raytracer::graph::SceneNode;
+use raytracer::light::{Light, GeometryLight, AREA_LIGHT_SAMPLES};
+use raytracer::texture::{Texture, ConstantTexture};
 
 pub struct Intersection {
-    t: f32,
-    position: Vector3,
-    normal: Vector3,
-    material: Material,
-    is_valid: bool
+    pub t: f32,
+    pub position: Vector3,
+    // Normal of the actual surface, which rays leave from.
+    pub normal: Vector3,
+    // Normal the surface is shaded with, interpolated over smooth meshes.
+    // Normal and bump maps are applied on top of it by `Material`.
+    pub shading_normal: Vector3,
+    // Surface coordinates of the hit and their derivatives, the tangents
+    // along which textures are laid out.
+    pub uv: Vector2,
+    pub dpdu: Vector3,
+    pub dpdv: Vector3,
+    // Only set by `World`, for the closest hit.
+    pub material: Option<Material>,
+    // Index in `World::lights` of the light sampling the emissive object hit.
+    pub light: Option<usize>,
+    // Time of the ray that hit, which rays leaving the surface keep.
+    pub time: f32,
+    pub is_valid: bool
 }
 
 impl Intersection {
-    fn new() -> Intersection {
+    pub fn new() -> Intersection {
         Intersection{
             t: f32::MAX,
             position: Vector3::new(0.0, 0.0, 0.0),
             normal: Vector3::new(0.0, 0.0, 0.0),
-            material: Material::new(Color::BLACK),
+            shading_normal: Vector3::new(0.0, 0.0, 0.0),
+            uv: Vector2::zero(),
+            dpdu: Vector3::zero(),
+            dpdv: Vector3::zero(),
+            material: None,
+            light: None,
+            time: 0.0,
             is_valid: false,
         }
     }
 }
 
+impl Intersection {
+    // Whether `ray` arrives on the side the normal points to, i.e. enters
+    // closed objects.
+    pub fn is_entering(&self, ray: Ray) -> bool {
+        dot(ray.direction, self.normal) < 0.0
+    }
+
+    // Ray leaving the surface in `direction`, starting slightly off it on
+    // the side `direction` points to so that it can't hit it again.
+    pub fn spawn_ray(&self, direction: Vector3) -> Ray {
+        let n = safe_normalize(self.normal);
+        let offset = if dot(direction, n) > 0.0 { SPAWN_OFFSET } else { -SPAWN_OFFSET };
+        Ray::new(self.position + offset * n, direction).with_time(self.time)
+    }
+}
+
+impl Default for Intersection {
+    fn default() -> Intersection {
+        Intersection::new()
+    }
+}
+
 pub trait Intersectable {
     fn intersect(&self, ray: Ray) -> Intersection;
+    fn bounds(&self) -> Aabb;
+
+    // Surface area, zero for unbounded surfaces which can't be sampled.
+    fn area(&self) -> f32 { 0.0 }
+
+    // Point picked on the surface, uniformly unless the geometry says
+    // otherwise. `u` is uniformly distributed in [0, 1)^2.
+    fn sample(&self, _u: Vector2) -> Option<SurfaceSample> { None }
+
+    // Density per unit area with which `sample` picks the point hit.
+    fn sample_pdf(&self, _intersection: &Intersection) -> f32 {
+        let area = self.area();
+        if area > 0.0 { 1.0 / area } else { 0.0 }
+    }
+
+    // Unbounded surfaces like planes can't be sampled by area, but seen from
+    // `position` they cover the whole hemisphere around the returned
+    // direction. None for the others and for points on the surface.
+    fn hemisphere(&self, _position: Vector3) -> Option<Vector3> { None }
+
+    // The geometry as a `TransformedGeometry`, so that further transforms
+    // are folded into it rather than wrapping it again.
+    fn as_transformed(&self) -> Option<&TransformedGeometry> { None }
+
     fn box_clone(&self) -> Box<Intersectable>;
 }
 
+// Point picked on a surface, with the normal and texture coordinates there.
+#[derive(Clone, Copy, Debug)]
+pub struct SurfaceSample {
+    pub position: Vector3,
+    pub normal: Vector3,
+    pub uv: Vector2,
+    // Density per unit area.
+    pub pdf: f32,
+}
+
 impl Clone for Box<Intersectable> {
     fn clone(&self) -> Box<Intersectable> {
         self.box_clone()
     }
 }
 
+// Points p such that dot(normal, p) + d = 0. Texture coordinates are the
+// position along two axes of the plane from `origin`, divided by `scale`.
 #[derive(Clone)]
-pub struct Plane { normal: Vector3, d: f32 }
+pub struct Plane {
+    normal: Vector3,
+    d: f32,
+    origin: Vector3,
+    tangent: Vector3,
+    bitangent: Vector3,
+    scale: f32,
+}
+
 impl Plane {
     pub fn new(normal: Vector3, d: f32) -> Plane {
-        Plane { normal: normal, d: d }
+        let (tangent, bitangent) = coordinate_system(safe_normalize(normal));
+        Plane {
+            normal: normal,
+            d: d,
+            origin: -d * normal,
+            tangent: tangent,
+            bitangent: bitangent,
+            scale: 1.0,
+        }
+    }
+
+    // Texture coordinates are (0, 0) at `origin` and grow by one every
+    // `scale` units.
+    pub fn with_mapping(mut self, origin: Vector3, scale: f32) -> Plane {
+        self.origin = origin;
+        self.scale = scale;
+        self
     }
 }
 
@@ -54,39 +158,527 @@ impl Sphere {
     pub fn new(position: Vector3, r: f32) -> Sphere {
         Sphere { position: position, r: r }
     }
+
+    fn fill_coordinates(&self, result: &mut Intersection) {
+        let (theta, phi) = spherical_angles(result.normal);
+        result.uv = spherical_uv(theta, phi);
+
+        let (sin_theta, cos_theta) = (sin(theta), cos(theta));
+        let (sin_phi, cos_phi) = (sin(phi), cos(phi));
+        result.dpdu = Vector3::new(-sin_phi, cos_phi, 0.0) * (2.0 * f32::consts::PI * self.r * sin_theta);
+        result.dpdv = Vector3::new(-cos_theta * cos_phi, -cos_theta * sin_phi, sin_theta) * (f32::consts::PI * self.r);
+    }
+}
+
+// Polar angle from +z and azimuth in [0, 2pi) of the normalized `n`.
+fn spherical_angles(n: Vector3) -> (f32, f32) {
+    let theta = acos(clamp(n.z, -1.0, 1.0));
+    let mut phi = atan2(n.y, n.x);
+    if phi < 0.0 {
+        phi += 2.0 * f32::consts::PI;
+    }
+    (theta, phi)
 }
 
-#[derive(Clone, Copy)]
+// Spherical coordinates on spheres: u goes once around the z axis
+// counterclockwise from +x, v from the bottom (-z) to the top.
+fn spherical_uv(theta: f32, phi: f32) -> Vector2 {
+    Vector2::new(phi / (2.0 * f32::consts::PI), 1.0 - theta / f32::consts::PI)
+}
+
+// Geometry placed in the world by an object-to-world transform, tested with
+// rays brought into its own space. Meshes share their triangles between
+// clones, so a mesh can be instanced many times for the cost of a transform
+// each.
+#[derive(Clone)]
+pub struct TransformedGeometry {
+    geometry: Box<Intersectable>,
+    transform: Transform,
+    // Exact area under similarity transforms, None when it has to be
+    // estimated.
+    area: Option<f32>,
+}
+
+// Samples per side of the grid estimating the area of transformed surfaces.
+const AREA_ESTIMATE_SAMPLES: usize = 32;
+
+impl TransformedGeometry {
+    pub fn new(geometry: Box<Intersectable>, transform: Transform) -> TransformedGeometry {
+        // Similarities scale every area by the same factor, the square of
+        // the scale.
+        let area = if transform.is_similarity() {
+            Some(geometry.area() * abs(transform.matrix().determinant3()).powf(2.0 / 3.0))
+        } else {
+            None
+        };
+
+        TransformedGeometry {
+            geometry: geometry,
+            transform: transform,
+            area: area,
+        }
+    }
+
+    // The same geometry placed by this transform followed by `transform`.
+    pub fn then(&self, transform: Transform) -> TransformedGeometry {
+        TransformedGeometry::new(self.geometry.clone(), self.transform.then(transform))
+    }
+
+    // Mean of the inverse sample density over a stratified grid of samples,
+    // exact when the area scale is the same everywhere.
+    fn estimate_area(&self) -> f32 {
+        if self.geometry.area() <= 0.0 {
+            return 0.0;
+        }
+
+        let n = AREA_ESTIMATE_SAMPLES;
+        let mut sum = 0.0;
+        let mut count = 0;
+        for i in 0..n {
+            for j in 0..n {
+                let u = Vector2::new((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32);
+                if let Some(sample) = self.sample(u) {
+                    sum += 1.0 / sample.pdf;
+                    count += 1;
+                }
+            }
+        }
+
+        if count == 0 { 0.0 } else { sum / count as f32 }
+    }
+}
+
+// How light scatters off a surface, shared between every object using it.
+#[derive(Clone)]
 pub struct Material {
-    albedo: Color,
+    surface: Surface,
+    // Beer-Lambert absorption coefficient, per unit of distance, of the
+    // inside of objects light can refract into.
+    absorption: Color,
+    // Radiance emitted on the side the normal points to, None for materials
+    // which don't emit.
+    emission: Option<Arc<Texture>>,
+    // Tangent space normals, x along dP/du, y along dP/dv and z along the
+    // shading normal, stored as colors remapped to [0, 1].
+    normal_map: Option<Arc<Texture>>,
+    // Heights along the shading normal, and their scale.
+    bump_map: Option<(Arc<Texture>, f32)>,
+}
+
+// Step in texture coordinates over which bump maps are differentiated.
+const BUMP_DELTA: f32 = 5e-4;
+
+// Parameters of the BSDF, either fixed or read from textures at every hit.
+#[derive(Clone)]
+enum Surface {
+    Fixed(Arc<Bsdf>),
+    Diffuse { albedo: Arc<Texture> },
+    Mirror { reflectance: Arc<Texture> },
+    Conductor { eta: Color, k: Color, roughness: Arc<Texture> },
+    Dielectric { ior: f32, roughness: Arc<Texture> },
+}
+
+// BSDF of a material at a point, built there without allocating.
+pub enum SurfaceBsdf<'a> {
+    Shared(&'a (Bsdf + 'static)),
+    Lambertian(Lambertian),
+    Mirror(Mirror),
+    Conductor(Conductor),
+    Dielectric(Dielectric),
+}
+
+impl<'a> ops::Deref for SurfaceBsdf<'a> {
+    type Target = Bsdf + 'static;
+    fn deref(&self) -> &(Bsdf + 'static) {
+        match *self {
+            SurfaceBsdf::Shared(bsdf) => bsdf,
+            SurfaceBsdf::Lambertian(ref bsdf) => bsdf,
+            SurfaceBsdf::Mirror(ref bsdf) => bsdf,
+            SurfaceBsdf::Conductor(ref bsdf) => bsdf,
+            SurfaceBsdf::Dielectric(ref bsdf) => bsdf,
+        }
+    }
+}
+
+// Transform of an object over time, interpolated between keyframes and
+// constant before the first one and after the last one. Translations and
+// stretches are interpolated linearly, rotations along the shortest arc.
+#[derive(Clone, Debug)]
+pub struct Motion {
+    // Sorted by time.
+    keyframes: Vec<Keyframe>,
+    // Placement of the whole motion, e.g. by the scene graph node holding
+    // the object.
+    after: Option<Transform>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Keyframe {
+    time: f32,
+    transform: Transform,
+    translation: Vector3,
+    rotation: Quaternion,
+    stretch: Matrix4,
+}
+
+impl Motion {
+    pub fn new(keyframes: Vec<(f32, Transform)>) -> Motion {
+        assert!(!keyframes.is_empty());
+        let mut keyframes: Vec<Keyframe> = keyframes.into_iter().map(|(time, transform)| {
+            let (translation, rotation, stretch) = transform.decompose();
+            Keyframe {
+                time: time,
+                transform: transform,
+                translation: translation,
+                rotation: rotation,
+                stretch: stretch,
+            }
+        }).collect();
+        keyframes.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+        Motion { keyframes: keyframes, after: None }
+    }
+
+    // Moves from where the object is to `transform` between times `start`
+    // and `end`.
+    pub fn linear(start: f32, end: f32, transform: Transform) -> Motion {
+        Motion::new(vec![(start, Transform::identity()), (end, transform)])
+    }
+
+    // The same motion followed by `transform`.
+    pub fn then(&self, transform: Transform) -> Motion {
+        Motion {
+            keyframes: self.keyframes.clone(),
+            after: Some(match self.after {
+                Some(after) => after.then(transform),
+                None => transform,
+            }),
+        }
+    }
+
+    pub fn transform(&self, time: f32) -> Transform {
+        let result = self.interpolate(time);
+        match self.after {
+            Some(after) => result.then(after),
+            None => result,
+        }
+    }
+
+    fn interpolate(&self, time: f32) -> Transform {
+        let i = self.keyframes.iter().position(|k| k.time > time).unwrap_or(self.keyframes.len());
+        if i == 0 {
+            return self.keyframes[0].transform;
+        }
+        if i == self.keyframes.len() {
+            return self.keyframes[i - 1].transform;
+        }
+
+        let (k0, k1) = (&self.keyframes[i - 1], &self.keyframes[i]);
+        let s = (time - k0.time) / (k1.time - k0.time);
+        let translation = k0.translation * (1.0 - s) + k1.translation * s;
+        let rotation = k0.rotation.slerp(k1.rotation, s);
+        let mut stretch = k0.stretch;
+        for row in 0..3 {
+            for column in 0..3 {
+                stretch.m[row][column] = k0.stretch.m[row][column] * (1.0 - s) + k1.stretch.m[row][column] * s;
+            }
+        }
+
+        // Stretches of opposite signs go through singular ones in between.
+        return Transform::compose(translation, rotation, stretch).unwrap_or(k0.transform);
+    }
+
+    // Bounds swept by `bounds` over the motion. Without rotation, boxes are
+    // transformed linearly between keyframes and stay within the union of
+    // the keyframe boxes. Rotating ones stay within the ball reached by the
+    // farthest corner around the translation.
+    pub fn bounds(&self, bounds: Aabb) -> Aabb {
+        let result = self.keyframe_bounds(bounds);
+        match self.after {
+            Some(after) => after.bounds(result),
+            None => result,
+        }
+    }
+
+    fn keyframe_bounds(&self, bounds: Aabb) -> Aabb {
+        let rotation = self.keyframes[0].rotation;
+        if self.keyframes.iter().all(|k| abs(k.rotation.dot(rotation)) > 1.0 - 1e-6) {
+            return self.keyframes.iter().fold(Aabb::empty(), |result, k| {
+                result.union(k.transform.bounds(bounds))
+            });
+        }
+
+        let corner = length(Vector3::new(max(abs(bounds.min.x), abs(bounds.max.x)),
+                                         max(abs(bounds.min.y), abs(bounds.max.y)),
+                                         max(abs(bounds.min.z), abs(bounds.max.z))));
+
+        // The Frobenius norm bounds how far a stretch moves points, and it
+        // is convex so interpolated stretches don't exceed the keyframes'.
+        let stretch = self.keyframes.iter().fold(0.0, |result, k| {
+            let m = &k.stretch.m;
+            let norm = sqrt((0..3).map(|i| (0..3).map(|j| square(m[i][j])).sum::<f32>()).sum());
+            max(result, norm)
+        });
+
+        let r = corner * stretch;
+        let r = Vector3::new(r, r, r);
+        self.keyframes.iter().fold(Aabb::empty(), |result, k| {
+            result.union(Aabb::new(k.translation - r, k.translation + r))
+        })
+    }
 }
 
 #[derive(Clone)]
 pub struct Object {
     geometry: Box<Intersectable>,
     material: Material,
+    // Whether the material was chosen for the object rather than defaulted,
+    // scene graph nodes only replace the latter.
+    own_material: bool,
+    motion: Option<Motion>,
 }
 impl Object {
     pub fn new(geometry: Box<Intersectable>, material: Material) -> Object {
         Object {
             geometry: geometry,
             material: material,
+            own_material: true,
+            motion: None,
+        }
+    }
+
+    pub fn with_material(mut self, material: Material) -> Object {
+        self.material = material;
+        self.own_material = true;
+        self
+    }
+
+    // Material used until the object is given its own.
+    pub fn with_default_material(mut self, material: Material) -> Object {
+        self.material = material;
+        self.own_material = false;
+        self
+    }
+
+    pub fn has_own_material(&self) -> bool {
+        self.own_material
+    }
+
+    // Places the object with `transform`, after any transform or motion it
+    // already had.
+    pub fn with_transform(mut self, transform: Transform) -> Object {
+        match self.motion {
+            Some(ref mut motion) => *motion = motion.then(transform),
+            None => {
+                let folded = self.geometry.as_transformed().map(|geometry| geometry.then(transform));
+                self.geometry = Box::new(match folded {
+                    Some(geometry) => geometry,
+                    None => TransformedGeometry::new(self.geometry, transform),
+                });
+            },
+        }
+        self
+    }
+
+    // Moves the object with `motion`, after any transform it already had.
+    pub fn with_motion(mut self, motion: Motion) -> Object {
+        self.motion = Some(motion);
+        self
+    }
+
+    // Moving objects are tested where they are at the time of the ray.
+    fn intersect(&self, ray: Ray) -> Intersection {
+        match self.motion {
+            Some(ref motion) => intersect_transformed(&*self.geometry, &motion.transform(ray.time), ray),
+            None => self.geometry.intersect(ray),
+        }
+    }
+
+    fn bounds(&self) -> Aabb {
+        let bounds = self.geometry.bounds();
+        match self.motion {
+            Some(ref motion) if bounds.is_finite() => motion.bounds(bounds),
+            _ => bounds,
         }
     }
 }
 
 impl Material {
+    // Lambertian material.
     pub fn new(albedo: Color) -> Material {
+        Material::from_bsdf(Lambertian::new(albedo))
+    }
+
+    pub fn from_bsdf<B: Bsdf + 'static>(bsdf: B) -> Material {
+        Material::from_surface(Surface::Fixed(Arc::new(bsdf)))
+    }
+
+    // Lambertian material with a textured albedo.
+    pub fn diffuse(albedo: Arc<Texture>) -> Material {
+        Material::from_surface(Surface::Diffuse { albedo: albedo })
+    }
+
+    pub fn mirror(reflectance: Arc<Texture>) -> Material {
+        Material::from_surface(Surface::Mirror { reflectance: reflectance })
+    }
+
+    // See `Conductor`, the roughness is read from the texture at every hit.
+    pub fn conductor(eta: Color, k: Color, roughness: Arc<Texture>) -> Material {
+        Material::from_surface(Surface::Conductor { eta: eta, k: k, roughness: roughness })
+    }
+
+    // See `Dielectric`, the roughness is read from the texture at every hit.
+    pub fn dielectric(ior: f32, roughness: Arc<Texture>) -> Material {
+        Material::from_surface(Surface::Dielectric { ior: ior, roughness: roughness })
+    }
+
+    fn from_surface(surface: Surface) -> Material {
         Material {
-            albedo: albedo,
+            surface: surface,
+            absorption: Color::BLACK,
+            emission: None,
+            normal_map: None,
+            bump_map: None,
+        }
+    }
+
+    // Makes light travelling inside the object fade, e.g. for colored glass.
+    pub fn with_absorption(mut self, absorption: Color) -> Material {
+        self.absorption = absorption;
+        self
+    }
+
+    // Turns objects using the material into light sources, black doesn't.
+    pub fn with_emission(mut self, emission: Color) -> Material {
+        self.emission = if emission.is_black() {
+            None
+        } else {
+            Some(Arc::new(ConstantTexture::new(emission)))
+        };
+        self
+    }
+
+    // Emission varying over the surface.
+    pub fn with_emission_texture(mut self, emission: Arc<Texture>) -> Material {
+        self.emission = Some(emission);
+        self
+    }
+
+    // BSDF at the point hit, with the texture values there.
+    pub fn bsdf<'a>(&'a self, intersection: &Intersection) -> SurfaceBsdf<'a> {
+        let (uv, p) = (intersection.uv, intersection.position);
+        match self.surface {
+            Surface::Fixed(ref bsdf) => SurfaceBsdf::Shared(&**bsdf),
+            Surface::Diffuse { ref albedo } => SurfaceBsdf::Lambertian(Lambertian::new(albedo.color(uv, p))),
+            Surface::Mirror { ref reflectance } => SurfaceBsdf::Mirror(Mirror::new(reflectance.color(uv, p))),
+            Surface::Conductor { eta, k, ref roughness } => {
+                SurfaceBsdf::Conductor(Conductor::new(eta, k, saturate(roughness.value(uv, p))))
+            },
+            Surface::Dielectric { ior, ref roughness } => {
+                SurfaceBsdf::Dielectric(Dielectric::new(ior, saturate(roughness.value(uv, p))))
+            },
+        }
+    }
+
+    pub fn with_normal_map(mut self, normal_map: Arc<Texture>) -> Material {
+        self.normal_map = Some(normal_map);
+        self
+    }
+
+    // `scale` converts the texture values into distances.
+    pub fn with_bump_map(mut self, bump_map: Arc<Texture>, scale: f32) -> Material {
+        self.bump_map = Some((bump_map, scale));
+        self
+    }
+
+    // Shading normal at the point hit, perturbed by the normal map and then
+    // the bump map.
+    pub fn shading_normal(&self, intersection: &Intersection) -> Vector3 {
+        let n = safe_normalize(intersection.shading_normal);
+        if self.normal_map.is_none() && self.bump_map.is_none() {
+            return n;
+        }
+
+        // Tangent frame following the texture coordinates.
+        let dpdu = intersection.dpdu - dot(intersection.dpdu, n) * n;
+        let dpdv = intersection.dpdv - dot(intersection.dpdv, n) * n;
+        if length_squared(dpdu) == 0.0 || length_squared(dpdv) == 0.0 {
+            return n;
+        }
+        let tangent = normalize(dpdu);
+        let mut bitangent = cross(n, tangent);
+        if dot(bitangent, dpdv) < 0.0 {
+            bitangent = -bitangent;
+        }
+
+        let (uv, p) = (intersection.uv, intersection.position);
+        let mut result = n;
+
+        if let Some(ref normal_map) = self.normal_map {
+            let c = normal_map.color(uv, p);
+            let local = Vector3::new(2.0 * c.red - 1.0, 2.0 * c.green - 1.0, 2.0 * c.blue - 1.0);
+            let mapped = safe_normalize(local.x * tangent + local.y * bitangent + local.z * n);
+            if dot(mapped, n) > 0.0 {
+                result = mapped;
+            }
+        }
+
+        if let Some((ref bump_map, scale)) = self.bump_map {
+            // Slopes of the height field along the tangents.
+            let height = bump_map.value(uv, p);
+            let du = bump_map.value(uv + Vector2::new(BUMP_DELTA, 0.0), p + BUMP_DELTA * intersection.dpdu) - height;
+            let dv = bump_map.value(uv + Vector2::new(0.0, BUMP_DELTA), p + BUMP_DELTA * intersection.dpdv) - height;
+            let slope_u = scale * du / (BUMP_DELTA * length(dpdu));
+            let slope_v = scale * dv / (BUMP_DELTA * length(dpdv));
+            result = safe_normalize(result - slope_u * tangent - slope_v * bitangent);
+        }
+
+        return result;
+    }
+
+    pub fn absorption(&self) -> Color {
+        self.absorption
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        self.emission.is_some()
+    }
+
+    pub fn emission_texture(&self) -> Option<&Arc<Texture>> {
+        self.emission.as_ref()
+    }
+
+    // Radiance emitted at the point hit.
+    pub fn emission(&self, intersection: &Intersection) -> Color {
+        match self.emission {
+            Some(ref emission) => emission.color(intersection.uv, intersection.position),
+            None => Color::BLACK,
         }
     }
 }
 
+// Everything there is to see: the objects, the lights and the camera. How
+// they are rendered is up to an `Integrator`.
 #[derive(Clone)]
 pub struct World {
     objects: Vec<Object>,
+    lights: Vec<Box<Light>>,
     camera: Camera,
+
+    // Radiance of rays escaping scenes without an environment light.
+    constant_background: Color,
+
+    // Graph the objects were flattened from, if any, kept to look nodes up.
+    // Shared between the copies of the world each render thread gets.
+    graph: Option<Arc<SceneNode>>,
+
+    // Objects with finite bounds go through the BVH, the others (planes) are
+    // tested one by one.
+    bvh: Bvh,
+    bvh_objects: Vec<usize>,
+    unbounded_objects: Vec<usize>,
+
+    // Index in `lights` of the light sampling each emissive object.
+    object_lights: Vec<Option<usize>>,
 }
 
 impl Intersectable for Plane {
@@ -97,15 +689,34 @@ impl Intersectable for Plane {
         if abs(denom) > TOLERANCE {
             let t = (-self.d - dot(self.normal, ray.origin)) / denom;
             if t > MIN_HIT_DISTANCE && t < result.t {
+                let offset = ray_point(ray, t) - self.origin;
                 result.is_valid = true;
                 result.t = t;
                 result.normal = self.normal;
+                result.shading_normal = self.normal;
+                result.uv = Vector2::new(dot(offset, self.tangent), dot(offset, self.bitangent)) * (1.0 / self.scale);
+                result.dpdu = self.tangent * self.scale;
+                result.dpdv = self.bitangent * self.scale;
             }
         }
 
         return result;
     }
 
+    fn bounds(&self) -> Aabb {
+        Aabb::infinite()
+    }
+
+    fn hemisphere(&self, position: Vector3) -> Option<Vector3> {
+        let side = dot(self.normal, position) + self.d;
+        if abs(side) <= TOLERANCE {
+            return None;
+        }
+
+        let normal = safe_normalize(self.normal);
+        Some(if side > 0.0 { -normal } else { normal })
+    }
+
     fn box_clone(&self) -> Box<Intersectable> {
         Box::new((*self).clone())
     }
@@ -132,12 +743,138 @@ impl Intersectable for Sphere {
                 result.is_valid = true;
                 result.t = t;
                 result.normal = safe_normalize(ray_point(ray, t) - self.position);
+                result.shading_normal = result.normal;
+                self.fill_coordinates(&mut result);
             }
         }
 
         return result;
     }
 
+    fn bounds(&self) -> Aabb {
+        let r = Vector3::new(self.r, self.r, self.r);
+        Aabb::new(self.position - r, self.position + r)
+    }
+
+    fn area(&self) -> f32 {
+        4.0 * f32::consts::PI * square(self.r)
+    }
+
+    fn sample(&self, u: Vector2) -> Option<SurfaceSample> {
+        let n = uniform_sample_sphere(u);
+        let (theta, phi) = spherical_angles(n);
+        Some(SurfaceSample {
+            position: self.position + self.r * n,
+            normal: n,
+            uv: spherical_uv(theta, phi),
+            pdf: 1.0 / self.area(),
+        })
+    }
+
+    fn box_clone(&self) -> Box<Intersectable> {
+        Box::new((*self).clone())
+    }
+}
+
+// Hit of `ray` with `geometry` placed by the object-to-world `transform`,
+// found with the ray brought into object space.
+pub fn intersect_transformed(geometry: &Intersectable, transform: &Transform, ray: Ray) -> Intersection {
+    let object_ray = transform.inverse().ray(ray);
+    let mut result = geometry.intersect(object_ray);
+    if !result.is_valid {
+        return result;
+    }
+
+    // The object ray was normalized, measure the hit distance again.
+    let position = transform.point(ray_point(object_ray, result.t));
+    result.t = length(position - ray.origin);
+    result.position = position;
+    result.normal = safe_normalize(transform.normal(result.normal));
+    result.shading_normal = safe_normalize(transform.normal(result.shading_normal));
+    result.dpdu = transform.vector(result.dpdu);
+    result.dpdv = transform.vector(result.dpdv);
+
+    return result;
+}
+
+// Ratio between areas around a point of a surface placed by `transform` in
+// world and object space, `normal` being the normalized world normal there.
+// With M the transform, it is |det M| / |transpose(M) normal|.
+fn area_scale(transform: &Transform, normal: Vector3) -> f32 {
+    let det = abs(transform.matrix().determinant3());
+    let m = transform.matrix().transpose();
+    return det / length(m.transform_vector(normal));
+}
+
+// The points `geometry` picks, moved by `transform`. Non-uniform scales
+// stretch some parts of the surface more than others, which changes the
+// density there.
+pub fn sample_transformed(geometry: &Intersectable, transform: &Transform, u: Vector2) -> Option<SurfaceSample> {
+    let sample = geometry.sample(u)?;
+    let normal = safe_normalize(transform.normal(sample.normal));
+    Some(SurfaceSample {
+        position: transform.point(sample.position),
+        normal: normal,
+        uv: sample.uv,
+        pdf: sample.pdf / area_scale(transform, normal),
+    })
+}
+
+// Density with which `sample_transformed` picks the world space hit.
+pub fn sample_pdf_transformed(geometry: &Intersectable, transform: &Transform, intersection: &Intersection) -> f32 {
+    let inverse = transform.inverse();
+    let object_hit = Intersection {
+        position: inverse.point(intersection.position),
+        normal: safe_normalize(inverse.normal(intersection.normal)),
+        uv: intersection.uv,
+        time: intersection.time,
+        is_valid: intersection.is_valid,
+        ..Intersection::new()
+    };
+    let normal = safe_normalize(intersection.normal);
+    geometry.sample_pdf(&object_hit) / area_scale(transform, normal)
+}
+
+// Hemisphere covered by `geometry` placed by `transform`, seen from `position`.
+pub fn hemisphere_transformed(geometry: &Intersectable, transform: &Transform, position: Vector3) -> Option<Vector3> {
+    let axis = geometry.hemisphere(transform.inverse().point(position))?;
+    Some(safe_normalize(transform.normal(axis)))
+}
+
+impl Intersectable for TransformedGeometry {
+    fn intersect(&self, ray: Ray) -> Intersection {
+        intersect_transformed(&*self.geometry, &self.transform, ray)
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.transform.bounds(self.geometry.bounds())
+    }
+
+    // Estimated on every call under non-uniform scales, which is only done
+    // for the objects becoming lights.
+    fn area(&self) -> f32 {
+        match self.area {
+            Some(area) => area,
+            None => self.estimate_area(),
+        }
+    }
+
+    fn sample(&self, u: Vector2) -> Option<SurfaceSample> {
+        sample_transformed(&*self.geometry, &self.transform, u)
+    }
+
+    fn sample_pdf(&self, intersection: &Intersection) -> f32 {
+        sample_pdf_transformed(&*self.geometry, &self.transform, intersection)
+    }
+
+    fn hemisphere(&self, position: Vector3) -> Option<Vector3> {
+        hemisphere_transformed(&*self.geometry, &self.transform, position)
+    }
+
+    fn as_transformed(&self) -> Option<&TransformedGeometry> {
+        Some(self)
+    }
+
     fn box_clone(&self) -> Box<Intersectable> {
         Box::new((*self).clone())
     }
@@ -146,137 +883,289 @@ impl Intersectable for Sphere {
 impl Intersectable for World {
     fn intersect(&self, ray: Ray) -> Intersection {
 
-        let mut result = Intersection::new();
+        // The material is only looked up for the closest hit.
+        let mut closest_object = None;
+        let mut closest_t = f32::MAX;
 
-        for object in self.objects.iter() {
-            let intersection = object.geometry.intersect(ray);
+        let mut result = self.bvh.intersect(ray, |i| {
+            let intersection = self.objects[self.bvh_objects[i]].intersect(ray);
+            if intersection.is_valid && intersection.t < closest_t {
+                closest_t = intersection.t;
+                closest_object = Some(self.bvh_objects[i]);
+            }
+            intersection
+        });
+
+        for &i in self.unbounded_objects.iter() {
+            let intersection = self.objects[i].intersect(ray);
             if intersection.is_valid && intersection.t < result.t {
                 result = intersection;
-                result.material = object.material;
+                closest_object = Some(i);
             }
         }
 
         result.position = ray_point(ray, result.t);
+        result.time = ray.time;
+        if let Some(i) = closest_object {
+            result.material = Some(self.objects[i].material.clone());
+            result.light = self.object_lights[i];
+        }
 
         return result;
     }
 
+    fn bounds(&self) -> Aabb {
+        self.objects.iter().fold(Aabb::empty(), |b, o| b.union(o.bounds()))
+    }
+
     fn box_clone(&self) -> Box<Intersectable> {
         Box::new((*self).clone())
     }
 }
 
 impl World {
-    pub fn new(objects: Vec<Object>, camera: Camera) -> World {
+    // Emissive objects with a finite area or without bounds, i.e. planes, are
+    // added to `lights`, moving ones along with their motion.
+    pub fn new(objects: Vec<Object>, lights: Vec<Box<Light>>, camera: Camera) -> World {
+        let mut lights = lights;
+        let mut object_lights = Vec::new();
+        for object in objects.iter() {
+            let emission = match object.material.emission_texture() {
+                Some(emission) if object.geometry.area() > 0.0 || !object.geometry.bounds().is_finite() => {
+                    emission.clone()
+                },
+                _ => {
+                    object_lights.push(None);
+                    continue;
+                },
+            };
+
+            object_lights.push(Some(lights.len()));
+            let mut light = GeometryLight::new(object.geometry.clone(), emission, AREA_LIGHT_SAMPLES);
+            if let Some(ref motion) = object.motion {
+                light = light.with_motion(motion.clone());
+            }
+            lights.push(Box::new(light));
+        }
+
+        let mut bounds = Vec::new();
+        let mut bvh_objects = Vec::new();
+        let mut unbounded_objects = Vec::new();
+
+        for (i, object) in objects.iter().enumerate() {
+            let b = object.bounds();
+            if b.is_finite() {
+                bounds.push(b);
+                bvh_objects.push(i);
+            } else {
+                unbounded_objects.push(i);
+            }
+        }
+
+        let constant_background = if lights.iter().any(|light| light.is_environment()) {
+            Color::BLACK
+        } else {
+            DEFAULT_BACKGROUND
+        };
+
         World {
             objects: objects,
+            lights: lights,
+            constant_background: constant_background,
             camera: camera,
+            graph: None,
+            bvh: Bvh::new(&bounds),
+            bvh_objects: bvh_objects,
+            unbounded_objects: unbounded_objects,
+            object_lights: object_lights,
         }
     }
 
-    fn cast_ray(&self, ray: Ray) -> Color {
-        let intersection = self.intersect(ray);
+    // World of the visible objects of the graph below `root`.
+    pub fn from_graph(root: SceneNode, lights: Vec<Box<Light>>, camera: Camera) -> World {
+        let mut result = World::new(root.flatten(), lights, camera);
+        result.graph = Some(Arc::new(root));
+        return result;
+    }
 
-        let result: Color;
-        let light_pos = Vector3::new(0.0, 0.0, 0.0);
+    pub fn graph(&self) -> Option<&SceneNode> {
+        self.graph.as_deref()
+    }
 
-        if intersection.is_valid {
-            let light_vec = light_pos - intersection.position;
-            let light_vec_length = length(light_vec);
+    // First node called `name` of the graph, depth first.
+    pub fn find_node(&self, name: &str) -> Option<&SceneNode> {
+        self.graph()?.find(name)
+    }
 
-            let shadow_ray = Ray::new(intersection.position, light_vec);
-            let test = self.intersect(shadow_ray);
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
 
-            if !test.is_valid || test.t > light_vec_length {
-                let n = safe_normalize(intersection.normal);
-                let v = light_vec / light_vec_length;
-                let ndotl = saturate(dot(n, v));
+    pub fn lights(&self) -> &[Box<Light>] {
+        &self.lights
+    }
 
-                result = (intersection.material.albedo * ndotl) / 3.1415957;
-            } else {
-                result = Color::BLACK;
-            }
-        } else {
-            result = Color::grey(0.2);
-        }
+    // Radiance of rays escaping the scene: the environment lights' or a
+    // constant grey without any.
+    pub fn background(&self, ray: Ray) -> Color {
+        self.lights.iter().fold(self.constant_background, |sum, light| sum + light.le(ray))
+    }
 
-        return result;
+    // Part of `background` no light accounts for, which integrators sampling
+    // the lights still have to add on misses.
+    pub fn constant_background(&self) -> Color {
+        self.constant_background
     }
 
-    pub fn raytrace(&self, image: &Image, sender: Sender<(u32, u32, Color)>) {
+    // Reference implementation testing every object, kept to validate and
+    // benchmark the BVH.
+    pub fn intersect_linear(&self, ray: Ray) -> Intersection {
+
+        let mut result = Intersection::new();
+
+        for (i, object) in self.objects.iter().enumerate() {
+            let intersection = object.intersect(ray);
+            if intersection.is_valid && intersection.t < result.t {
+                result = intersection;
+                result.material = Some(object.material.clone());
+                result.light = self.object_lights[i];
+            }
+        }
 
-        let width = image.width;
-        let height = image.height;
-        let x_slices = 2;
-        let y_slices = 2;
+        result.position = ray_point(ray, result.t);
+        result.time = ray.time;
 
-        for i in 0..x_slices {
-            let x_beg = i * (width / x_slices);
-            let x_end = (i + 1) * (width / x_slices);
+        return result;
+    }
 
-            for j in 0..y_slices {
-                let y_beg = j * (height / y_slices);
-                let y_end = (j + 1) * (height / y_slices);
+    // Closest light surface hit by `ray`, with its emitted radiance.
+    pub fn intersect_lights(&self, ray: Ray) -> Option<(f32, Color, &Light)> {
+        let mut result: Option<(f32, Color, &Light)> = None;
 
-                self.spawn_thread(&sender, x_beg, x_end, y_beg, y_end, image);
+        for light in self.lights.iter() {
+            if let Some((t, radiance)) = light.intersect(ray) {
+                if result.is_none_or(|(closest, _, _)| t < closest) {
+                    result = Some((t, radiance, &**light));
+                }
             }
         }
+
+        return result;
     }
 
-    fn spawn_thread(&self, sender: &Sender<(u32, u32, Color)>,
-                    x_slice_begin: u32, x_slice_end: u32,
-                    y_slice_begin: u32, y_slice_end: u32, image: &Image)
-    {
-        let world = self.clone();
-        let s = sender.clone();
+    // Whether something lies between `position` and the point `distance`
+    // away in `direction` at `time`.
+    pub fn is_occluded(&self, position: Vector3, direction: Vector3, distance: f32, time: f32) -> bool {
+        // The light itself may be an object, don't let it shadow itself.
+        let test = self.intersect(Ray::new(position, direction).with_time(time));
+        test.is_valid && test.t < distance - MIN_HIT_DISTANCE
+    }
+}
 
-        let width = image.width;
-        let height = image.height;
-        let samples = image.samples;
+unsafe impl Send for World {}
+unsafe impl Sync for World {}
 
-        thread::spawn(move || {
-            world.raytrace_sub(s, x_slice_begin, x_slice_end,
-                               y_slice_begin, y_slice_end,
-                               width, height, samples);
-        });
-    }
+const DEFAULT_BACKGROUND: Color = Color::new(0.2, 0.2, 0.2);
 
-    fn raytrace_sub(&self, sender: Sender<(u32, u32, Color)>,
-                    x_slice_begin: u32, x_slice_end: u32,
-                    y_slice_begin: u32, y_slice_end: u32,
-                    width: u32, height: u32, samples: u32) {
+pub const MIN_HIT_DISTANCE: f32 = 1e-3;
+pub const SPAWN_OFFSET: f32 = 1e-4;
+pub const TOLERANCE: f32 = 1e-5;
 
-        let inv_image_width = 1.0 / width as f32;
-        let inv_image_height = 1.0 / height as f32;
 
-        let between = Range::new(0.0, 1.0);
-        let mut rng = rand::thread_rng();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raytracer::mesh::Triangle;
 
-        for y in y_slice_begin..y_slice_end {
-            for x in x_slice_begin..x_slice_end {
+    fn stretched(geometry: Box<Intersectable>) -> TransformedGeometry {
+        let transform = Transform::scale(Vector3::new(3.0, 1.0, 0.5))
+            .then(Transform::rotate(Vector3::unit_z(), 90.0))
+            .then(Transform::translate(Vector3::new(0.0, 0.0, 2.0)));
+        TransformedGeometry::new(geometry, transform)
+    }
 
-                let mut color = Color::BLACK;
-                for _s in 0..samples {
-                    let _x = x as f32 + between.ind_sample(&mut rng);
-                    let _y = y as f32 + between.ind_sample(&mut rng);
+    #[test]
+    fn hit_distances_are_measured_in_world_space() {
+        let sphere = stretched(Box::new(Sphere::new(Vector3::zero(), 1.0)));
 
-                    let u = _x * inv_image_width * 2.0 - 1.0;
-                    let v = _y * inv_image_height * 2.0 - 1.0;
+        // The x axis of the sphere now runs along y, 3 units out.
+        let hit = sphere.intersect(Ray::new(Vector3::new(0.0, -10.0, 2.0), Vector3::unit_y()));
+        assert!(hit.is_valid);
+        assert!(abs(hit.t - 7.0) < 1e-3, "{}", hit.t);
+        assert!(length(hit.normal - Vector3::new(0.0, -1.0, 0.0)) < 1e-3);
 
-                    let ray = self.camera.get_ray(u, v);
-                    color += self.cast_ray(ray);
-                }
+        let hit = sphere.intersect(Ray::new(Vector3::new(0.0, 0.0, 10.0), -Vector3::unit_z()));
+        assert!(hit.is_valid);
+        assert!(abs(hit.t - 7.5) < 1e-3, "{}", hit.t);
 
-                let final_color = color / samples as f32;
-                sender.send((x, y, final_color)).unwrap();
-            }
+        let miss = sphere.intersect(Ray::new(Vector3::new(2.0, 0.0, 10.0), -Vector3::unit_z()));
+        assert!(!miss.is_valid);
+    }
+
+    #[test]
+    fn motion_interpolates_rotations_and_bounds_them() {
+        let end = Transform::rotate(Vector3::unit_z(), 90.0).then(Transform::translate(Vector3::new(2.0, 0.0, 0.0)));
+        let motion = Motion::linear(0.0, 1.0, end);
+
+        let half = motion.transform(0.5);
+        let p = half.point(Vector3::unit_x());
+        let expected = Vector3::new(1.0 + sqrt(0.5), sqrt(0.5), 0.0);
+        assert!(length(p - expected) < 1e-4, "{:?}", p);
+
+        let bounds = motion.bounds(Aabb::new(Vector3::new(0.5, -0.5, -0.5), Vector3::new(1.5, 0.5, 0.5)));
+        for i in 0..=10 {
+            let corner = motion.transform(i as f32 / 10.0).point(Vector3::new(1.5, 0.5, 0.5));
+            assert!(bounds.min.x <= corner.x && corner.x <= bounds.max.x);
+            assert!(bounds.min.y <= corner.y && corner.y <= bounds.max.y);
         }
     }
-}
 
-unsafe impl Send for World {}
-unsafe impl Sync for World {}
+    #[test]
+    fn non_uniform_scales_change_area_and_density() {
+        let p = [Vector3::zero(), Vector3::unit_x(), Vector3::new(0.0, 1.0, 1.0)];
+        let triangle = stretched(Box::new(Triangle::new(p[0], p[1], p[2])));
+
+        let q: Vec<Vector3> = p.iter().map(|&p| triangle.transform.point(p)).collect();
+        let area = 0.5 * length(cross(q[1] - q[0], q[2] - q[0]));
+        assert!(abs(triangle.area() - area) < 1e-4 * area, "{} != {}", triangle.area(), area);
 
-const MIN_HIT_DISTANCE: f32 = 1e-3;
-const TOLERANCE: f32 = 1e-5;
+        let sample = triangle.sample(Vector2::new(0.3, 0.6)).unwrap();
+        assert!(abs(sample.pdf * area - 1.0) < 1e-4, "{}", sample.pdf * area);
 
+        let hit = Intersection {
+            position: sample.position,
+            normal: sample.normal,
+            is_valid: true,
+            ..Intersection::new()
+        };
+        assert!(abs(triangle.sample_pdf(&hit) * area - 1.0) < 1e-4);
+    }
+
+    #[test]
+    fn transforms_are_folded_with_exact_areas() {
+        let object = Object::new(Box::new(Sphere::new(Vector3::zero(), 1.0)), Material::new(Color::WHITE))
+            .with_transform(Transform::rotate(Vector3::unit_z(), 30.0))
+            .with_transform(Transform::scale(Vector3::new(2.0, 2.0, 2.0)));
+
+        let transformed = object.geometry.as_transformed().unwrap();
+        assert!(transformed.geometry.as_transformed().is_none());
+        let area = transformed.area.unwrap();
+        assert!(abs(area - 16.0 * f32::consts::PI) < 1e-3, "{}", area);
+    }
+
+    #[test]
+    fn moving_emitters_are_sampled_where_they_are() {
+        let motion = Motion::linear(0.0, 1.0, Transform::translate(Vector3::new(4.0, 0.0, 0.0)));
+        let light = GeometryLight::new(Box::new(Sphere::new(Vector3::zero(), 1.0)),
+                                       Arc::new(ConstantTexture::new(Color::WHITE)), 1)
+            .with_motion(motion);
+
+        let position = Vector3::new(2.0, 0.0, 0.0);
+        for &(time, center) in [(0.0, Vector3::zero()), (1.0, Vector3::new(4.0, 0.0, 0.0))].iter() {
+            let sample = light.sample(position, Vector2::new(0.3, 0.6), time);
+            let point = position + sample.direction * sample.distance;
+            assert!(abs(length(point - center) - 1.0) < 1e-3, "{:?}", point);
+            assert!(light.pdf(position, safe_normalize(center - position), time) > 0.0);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::f32;
+use std::time::Instant;
+
+use math::*;
+use raytracer::*;
+
+extern crate rand;
+use self::rand::distributions::{IndependentSample, Range};
+
+// Compares `World::intersect` (BVH) against the linear loop over every object
+// on a scene made of many spheres and a finely tessellated mesh.
+pub fn run_bvh_benchmark() {
+    let sphere_count = 4096;
+    let ray_count = 100000;
+
+    let between = Range::new(-10.0, 10.0);
+    let mut rng = rand::thread_rng();
+
+    let material = Material::new(Color::WHITE);
+    let mut objects = Vec::new();
+    for _ in 0..sphere_count {
+        let p = Vector3::new(between.ind_sample(&mut rng),
+                             between.ind_sample(&mut rng),
+                             between.ind_sample(&mut rng));
+        objects.push(make_sphere(p, 0.1, material.clone()));
+    }
+    objects.push(make_tessellated_sphere(Vector3::zero(), 3.0, 256, 128, material.clone()));
+    objects.push(make_plane(Vector3::new(0.0, 0.0, 1.0), 12.0, material));
+
+    let object_count = objects.len();
+    let build_start = Instant::now();
+    let world = World::new(objects, Vec::new(), Camera::new(1, 1, 1.0));
+    let build_time = seconds(build_start);
+
+    let rays: Vec<Ray> = (0..ray_count).map(|_| {
+        let target = Vector3::new(between.ind_sample(&mut rng),
+                                  between.ind_sample(&mut rng),
+                                  between.ind_sample(&mut rng));
+        Ray::new(Vector3::new(0.0, -30.0, 0.0), target - Vector3::new(0.0, -30.0, 0.0))
+    }).collect();
+
+    let bvh_start = Instant::now();
+    let bvh_hits: Vec<f32> = rays.iter().map(|&ray| world.intersect(ray).t).collect();
+    let bvh_time = seconds(bvh_start);
+
+    let linear_start = Instant::now();
+    let linear_hits: Vec<f32> = rays.iter().map(|&ray| world.intersect_linear(ray).t).collect();
+    let linear_time = seconds(linear_start);
+
+    let mismatches = bvh_hits.iter().zip(linear_hits.iter())
+        .filter(|&(a, b)| abs(a - b) > 1e-3 * max(1.0, abs(*b)))
+        .count();
+
+    println!("{} objects, {} rays, BVH built in {}s", object_count, ray_count, build_time);
+    println!("BVH:    {}s ({} rays/s)", bvh_time, ray_count as f32 / bvh_time);
+    println!("Linear: {}s ({} rays/s)", linear_time, ray_count as f32 / linear_time);
+    println!("Speedup: {}x, {} mismatching hits", linear_time / bvh_time, mismatches);
+}
+
+fn make_tessellated_sphere(center: Vector3, r: f32, slices: u32, stacks: u32,
+                           material: Material) -> Object {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut indices = Vec::new();
+
+    for j in 0..stacks + 1 {
+        let theta = f32::consts::PI * j as f32 / stacks as f32;
+        for i in 0..slices + 1 {
+            let phi = 2.0 * f32::consts::PI * i as f32 / slices as f32;
+            let n = Vector3::new(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            positions.push(center + r * n);
+            normals.push(n);
+        }
+    }
+
+    let row = slices + 1;
+    for j in 0..stacks {
+        for i in 0..slices {
+            let a = j * row + i;
+            let b = a + row;
+            indices.extend_from_slice(&[a, b, a + 1, a + 1, b, b + 1]);
+        }
+    }
+
+    make_mesh(positions, normals, Vec::new(), indices, material)
+}
+
+fn seconds(start: Instant) -> f32 {
+    let elapsed = start.elapsed();
+    elapsed.as_secs() as f32 + (elapsed.subsec_nanos() as f32) * 1e-9
+}
@@ -0,0 +1,143 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use math::*;
+use utils::image::*;
+
+// Loads a Radiance RGBE (.hdr) image, flat or run-length encoded. Only the
+// usual `-Y height +X width` orientation is supported.
+pub fn load_hdr<P: AsRef<Path>>(path: P) -> Result<LinearImage, ImageError> {
+    let path = path.as_ref();
+    let error = |message: &str| ImageError::Format { path: path.to_path_buf(), message: message.to_string() };
+
+    let mut data = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut data))
+        .map_err(|error| ImageError::Io { path: path.to_path_buf(), error: error })?;
+
+    let mut reader = ByteReader { data: &data, position: 0 };
+
+    let magic = reader.line().ok_or_else(|| error("empty file"))?;
+    if !magic.starts_with("#?") {
+        return Err(error("not a Radiance HDR file"));
+    }
+
+    // Header variables end with an empty line.
+    loop {
+        let line = reader.line().ok_or_else(|| error("truncated header"))?;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return Err(error("unsupported pixel format, expected 32-bit_rle_rgbe"));
+        }
+    }
+
+    let resolution = reader.line().ok_or_else(|| error("missing resolution"))?;
+    let tokens: Vec<&str> = resolution.split_whitespace().collect();
+    if tokens.len() != 4 || tokens[0] != "-Y" || tokens[2] != "+X" {
+        return Err(error("unsupported resolution line, expected '-Y <height> +X <width>'"));
+    }
+    let height = tokens[1].parse::<usize>().map_err(|_| error("invalid height"))?;
+    let width = tokens[3].parse::<usize>().map_err(|_| error("invalid width"))?;
+    if width == 0 || height == 0 {
+        return Err(error("empty image"));
+    }
+
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        reader.scanline(&mut scanline).ok_or_else(|| error("truncated pixel data"))?;
+        pixels.extend(scanline.iter().map(|&rgbe| rgbe_to_color(rgbe)));
+    }
+
+    Ok(LinearImage {
+        width: width,
+        height: height,
+        pixels: pixels,
+    })
+}
+
+fn rgbe_to_color(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::BLACK;
+    }
+
+    let scale = (2.0f32).powi(rgbe[3] as i32 - (128 + 8));
+    Color::new((rgbe[0] as f32 + 0.5) * scale,
+               (rgbe[1] as f32 + 0.5) * scale,
+               (rgbe[2] as f32 + 0.5) * scale)
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let result = self.data.get(self.position).cloned();
+        self.position += 1;
+        return result;
+    }
+
+    fn line(&mut self) -> Option<String> {
+        if self.position >= self.data.len() {
+            return None;
+        }
+
+        let rest = &self.data[self.position..];
+        let end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+        self.position += end + 1;
+        Some(String::from_utf8_lossy(&rest[..end]).trim().to_string())
+    }
+
+    fn scanline(&mut self, scanline: &mut [[u8; 4]]) -> Option<()> {
+        let width = scanline.len();
+        let first = [self.byte()?, self.byte()?, self.byte()?, self.byte()?];
+
+        // New-style run-length encoding: the four components are stored one
+        // after the other, each as runs and literal sequences.
+        let is_rle = (8..0x8000).contains(&width) && first[0] == 2 && first[1] == 2 && first[2] < 128;
+        if !is_rle {
+            scanline[0] = first;
+            for pixel in scanline.iter_mut().skip(1) {
+                *pixel = [self.byte()?, self.byte()?, self.byte()?, self.byte()?];
+            }
+            return Some(());
+        }
+
+        if ((first[2] as usize) << 8 | first[3] as usize) != width {
+            return None;
+        }
+
+        for component in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = self.byte()? as usize;
+                if count > 128 {
+                    let count = count - 128;
+                    let value = self.byte()?;
+                    if count == 0 || x + count > width {
+                        return None;
+                    }
+                    for pixel in scanline[x..x + count].iter_mut() {
+                        pixel[component] = value;
+                    }
+                    x += count;
+                } else {
+                    if count == 0 || x + count > width {
+                        return None;
+                    }
+                    for pixel in scanline[x..x + count].iter_mut() {
+                        pixel[component] = self.byte()?;
+                    }
+                    x += count;
+                }
+            }
+        }
+
+        return Some(());
+    }
+}
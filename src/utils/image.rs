@@ -1,6 +1,10 @@
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
+use std::io;
 use std::io::BufWriter;
 use std::os::raw::c_void;
+use std::path::{Path, PathBuf};
 
 use math::Color;
 
@@ -44,16 +48,37 @@ impl Image {
         return result;
     }
 
-    pub fn write_png(&self, filepath: String) -> () {
-        let file = File::create(filepath).unwrap();
-        let ref mut bufwriter = BufWriter::new(file);
+    pub fn write_png(&self, filepath: String) -> io::Result<()> {
+        let file = File::create(filepath)?;
+        let bufwriter = &mut BufWriter::new(file);
 
         let mut encoder = png::Encoder::new(bufwriter, self.width, self.height);
         encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
-        let mut writer = encoder.write_header().unwrap();
+        let mut writer = encoder.write_header()?;
 
         let data = self.get_writable_data();
-        writer.write_image_data(&data).unwrap();
+        writer.write_image_data(&data)?;
+
+        return Ok(());
+    }
+
+    // Copy of the `width` x `height` pixels starting at (`x`, `y`).
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Image {
+        assert!(x + width <= self.width && y + height <= self.height);
+        let mut result = Image::new(width, height, self.samples);
+        for j in 0..height {
+            let begin = (x + (y + j) * self.width) as usize;
+            let row = (j * width) as usize;
+            result.data[row..row + width as usize].copy_from_slice(&self.data[begin..begin + width as usize]);
+        }
+
+        return result;
+    }
+
+    pub fn clear(&mut self) {
+        for c in self.data.iter_mut() {
+            *c = Color::BLACK;
+        }
     }
 
     pub fn set_pixel_color(&mut self, i: u32, j: u32, color: Color) {
@@ -66,3 +91,80 @@ impl Image {
         vec_void_ptr(&self.data)
     }
 }
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io { path: PathBuf, error: io::Error },
+    Format { path: PathBuf, message: String },
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ImageError::Io { ref path, ref error } =>
+                write!(f, "{}: {}", path.display(), error),
+            ImageError::Format { ref path, ref message } =>
+                write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl Error for ImageError {}
+
+// Linear color values, row after row from the top of the image.
+pub struct LinearImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+// Loads a PNG image. Values are used as they are stored, the same way
+// rendered images are written, and alpha is ignored.
+pub fn load_png<P: AsRef<Path>>(path: P) -> Result<LinearImage, ImageError> {
+    let path = path.as_ref();
+    let format_error = |error: png::DecodingError| ImageError::Format {
+        path: path.to_path_buf(),
+        message: error.to_string(),
+    };
+
+    let file = File::open(path).map_err(|error| ImageError::Io { path: path.to_path_buf(), error: error })?;
+
+    // Palettes are expanded and 16 bit channels reduced to 8 bits.
+    let decoder = png::Decoder::new(file);
+    let (info, mut reader) = decoder.read_info().map_err(&format_error)?;
+    let mut data = vec![0u8; info.buffer_size()];
+    reader.next_frame(&mut data).map_err(&format_error)?;
+
+    let width = info.width as usize;
+    let height = info.height as usize;
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::RGB => 3,
+        png::ColorType::RGBA => 4,
+        png::ColorType::Indexed => return Err(ImageError::Format {
+            path: path.to_path_buf(),
+            message: "unexpected indexed colors".to_string(),
+        }),
+    };
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let row = &data[y * info.line_size..];
+        for x in 0..width {
+            let texel = &row[x * channels..];
+            let value = |i: usize| texel[i] as f32 / 255.0;
+            pixels.push(if channels < 3 {
+                Color::grey(value(0))
+            } else {
+                Color::new(value(0), value(1), value(2))
+            });
+        }
+    }
+
+    Ok(LinearImage {
+        width: width,
+        height: height,
+        pixels: pixels,
+    })
+}
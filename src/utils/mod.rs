@@ -1,8 +1,23 @@
 pub mod image;
 pub use self::image::*;
 
+pub mod hdr;
+pub use self::hdr::*;
+
+pub mod obj;
+pub use self::obj::*;
+
+pub mod scene;
+pub use self::scene::*;
+
+pub mod watch;
+pub use self::watch::*;
+
+pub mod bench;
+pub use self::bench::*;
+
 use std::os::raw::c_void;
 
-pub fn vec_void_ptr<T>(vec: &Vec<T>) -> *const c_void {
+pub fn vec_void_ptr<T>(vec: &[T]) -> *const c_void {
     vec.as_ptr() as *const c_void
 }
\ No newline at end of file
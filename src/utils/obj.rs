@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use math::*;
+use raytracer::*;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io { path: PathBuf, error: io::Error },
+    Parse { path: PathBuf, line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ObjError::Io { ref path, ref error } =>
+                write!(f, "{}: {}", path.display(), error),
+            ObjError::Parse { ref path, line, ref message } =>
+                write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+// Loads a Wavefront OBJ file and its material libraries. Every group / material
+// pair becomes its own `Object`, faces without a `usemtl` statement or with
+// a material none of the libraries define use `default_material`.
+pub fn load_obj<P: AsRef<Path>>(path: P, default_material: Material) -> Result<Vec<Object>, ObjError> {
+    let path = path.as_ref();
+    let source = read_file(path)?;
+
+    let mut parser = ObjParser::new(path, default_material);
+    for (i, line) in source.lines().enumerate() {
+        parser.line = i + 1;
+        parser.parse_line(line)?;
+    }
+
+    let result = parser.meshes.into_iter()
+        .filter(|mesh| !mesh.indices.is_empty())
+        .map(|mesh| mesh.build())
+        .collect();
+
+    return Ok(result);
+}
+
+pub fn load_mtl<P: AsRef<Path>>(path: P) -> Result<HashMap<String, Material>, ObjError> {
+    let path = path.as_ref();
+    let source = read_file(path)?;
+
+    let mut result = HashMap::new();
+    // Name, diffuse color and emitted radiance of the material being read.
+    let mut current: Option<(String, Color, Color)> = None;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let error = |message: String| ObjError::Parse {
+            path: path.to_path_buf(),
+            line: line_number,
+            message: message,
+        };
+
+        let mut tokens = strip_comment(line).split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "newmtl" => {
+                if args.is_empty() {
+                    return Err(error("newmtl without a material name".to_string()));
+                }
+                if let Some((name, albedo, emission)) = current.take() {
+                    result.insert(name, Material::new(albedo).with_emission(emission));
+                }
+                current = Some((args.join(" "), Color::grey(0.8), Color::BLACK));
+            },
+            "Kd" => {
+                let albedo = match current {
+                    Some((_, ref mut albedo, _)) => albedo,
+                    None => return Err(error("Kd before any newmtl statement".to_string())),
+                };
+                let v = parse_floats(&args, 3, 3).map_err(&error)?;
+                *albedo = Color::new(v[0], v[1], v[2]);
+            },
+            "Ke" => {
+                let emission = match current {
+                    Some((_, _, ref mut emission)) => emission,
+                    None => return Err(error("Ke before any newmtl statement".to_string())),
+                };
+                let v = parse_floats(&args, 3, 3).map_err(&error)?;
+                *emission = Color::new(v[0], v[1], v[2]);
+            },
+            _ => (),
+        }
+    }
+
+    if let Some((name, albedo, emission)) = current.take() {
+        result.insert(name, Material::new(albedo).with_emission(emission));
+    }
+
+    return Ok(result);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct VertexKey {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+struct MeshBuilder {
+    material: Material,
+    // Whether the material comes from the libraries rather than being the
+    // default one.
+    own_material: bool,
+    positions: Vec<Vector3>,
+    uvs: Vec<Option<Vector2>>,
+    normals: Vec<Option<Vector3>>,
+    indices: Vec<u32>,
+    vertices: HashMap<VertexKey, u32>,
+}
+
+impl MeshBuilder {
+    fn new(material: Material, own_material: bool) -> MeshBuilder {
+        MeshBuilder {
+            material: material,
+            own_material: own_material,
+            positions: Vec::new(),
+            uvs: Vec::new(),
+            normals: Vec::new(),
+            indices: Vec::new(),
+            vertices: HashMap::new(),
+        }
+    }
+
+    fn add_vertex(&mut self, key: VertexKey, positions: &[Vector3], uvs: &[Vector2],
+                  normals: &[Vector3]) -> u32 {
+        if let Some(&index) = self.vertices.get(&key) {
+            return index;
+        }
+
+        let index = self.positions.len() as u32;
+        self.positions.push(positions[key.position]);
+        self.uvs.push(key.uv.map(|i| uvs[i]));
+        self.normals.push(key.normal.map(|i| normals[i]));
+        self.vertices.insert(key, index);
+
+        return index;
+    }
+
+    fn build(self) -> Object {
+        let uvs = if self.uvs.iter().any(|uv| uv.is_some()) {
+            self.uvs.iter().map(|uv| uv.unwrap_or(Vector2::zero())).collect()
+        } else {
+            Vec::new()
+        };
+
+        // Meshes without any normal are shaded flat. When only some vertices
+        // lack one, it is rebuilt from the area weighted normals of the faces
+        // sharing that vertex.
+        let normals = if self.normals.iter().any(|n| n.is_some()) {
+            let mut accumulated = vec![Vector3::zero(); self.positions.len()];
+            for face in self.indices.chunks(3) {
+                let p0 = self.positions[face[0] as usize];
+                let p1 = self.positions[face[1] as usize];
+                let p2 = self.positions[face[2] as usize];
+                let n = cross(p1 - p0, p2 - p0);
+                for &i in face {
+                    accumulated[i as usize] += n;
+                }
+            }
+
+            self.normals.iter().zip(accumulated.iter())
+                .map(|(n, &a)| n.unwrap_or(safe_normalize(a)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let result = make_mesh(self.positions, normals, uvs, self.indices, self.material.clone());
+        return if self.own_material { result } else { result.with_default_material(self.material) };
+    }
+}
+
+struct ObjParser {
+    path: PathBuf,
+    line: usize,
+
+    positions: Vec<Vector3>,
+    uvs: Vec<Vector2>,
+    normals: Vec<Vector3>,
+
+    materials: HashMap<String, Material>,
+    default_material: Material,
+    current_material: String,
+    current_group: String,
+
+    meshes: Vec<MeshBuilder>,
+    mesh_lookup: HashMap<(String, String), usize>,
+}
+
+impl ObjParser {
+    fn new(path: &Path, default_material: Material) -> ObjParser {
+        ObjParser {
+            path: path.to_path_buf(),
+            line: 0,
+            positions: Vec::new(),
+            uvs: Vec::new(),
+            normals: Vec::new(),
+            materials: HashMap::new(),
+            default_material: default_material,
+            current_material: String::new(),
+            current_group: String::new(),
+            meshes: Vec::new(),
+            mesh_lookup: HashMap::new(),
+        }
+    }
+
+    fn error(&self, message: String) -> ObjError {
+        ObjError::Parse {
+            path: self.path.clone(),
+            line: self.line,
+            message: message,
+        }
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), ObjError> {
+        let mut tokens = strip_comment(line).split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => return Ok(()),
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => {
+                let v = parse_floats(&args, 3, 4).map_err(|e| self.error(e))?;
+                self.positions.push(Vector3::new(v[0], v[1], v[2]));
+            },
+            "vt" => {
+                let v = parse_floats(&args, 1, 3).map_err(|e| self.error(e))?;
+                let v1 = if v.len() > 1 { v[1] } else { 0.0 };
+                self.uvs.push(Vector2::new(v[0], v1));
+            },
+            "vn" => {
+                let v = parse_floats(&args, 3, 3).map_err(|e| self.error(e))?;
+                self.normals.push(safe_normalize(Vector3::new(v[0], v[1], v[2])));
+            },
+            "f" => self.parse_face(&args)?,
+            "g" | "o" => {
+                self.current_group = args.join(" ");
+            },
+            "usemtl" => {
+                // Materials missing from the libraries fall back to the
+                // default one in `current_mesh`.
+                self.current_material = args.join(" ");
+            },
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err(self.error("mtllib without a file name".to_string()));
+                }
+                let directory = self.path.parent().unwrap_or(Path::new("")).to_path_buf();
+                for file in args.iter() {
+                    let materials = load_mtl(directory.join(file))?;
+                    self.materials.extend(materials);
+                }
+            },
+            // Smoothing groups, lines, points and the less common statements
+            // have no meaning for the renderer.
+            _ => (),
+        }
+
+        return Ok(());
+    }
+
+    fn parse_face(&mut self, args: &[&str]) -> Result<(), ObjError> {
+        if args.len() < 3 {
+            return Err(self.error(format!("face needs at least 3 vertices, got {}", args.len())));
+        }
+
+        let mut keys = Vec::with_capacity(args.len());
+        for arg in args.iter() {
+            keys.push(self.parse_vertex(arg)?);
+        }
+
+        let mesh = self.current_mesh();
+        let builder = &mut self.meshes[mesh];
+        let (positions, uvs, normals) = (&self.positions, &self.uvs, &self.normals);
+        let indices: Vec<u32> = keys.iter()
+            .map(|&key| builder.add_vertex(key, positions, uvs, normals))
+            .collect();
+
+        // Polygons are triangulated as a fan around their first vertex.
+        for i in 1..indices.len() - 1 {
+            builder.indices.push(indices[0]);
+            builder.indices.push(indices[i]);
+            builder.indices.push(indices[i + 1]);
+        }
+
+        return Ok(());
+    }
+
+    fn parse_vertex(&self, arg: &str) -> Result<VertexKey, ObjError> {
+        let mut parts = arg.split('/');
+
+        let position = match parts.next() {
+            Some(p) => self.parse_index(p, self.positions.len(), "position")?,
+            None => return Err(self.error(format!("invalid face vertex '{}'", arg))),
+        };
+
+        let uv = match parts.next() {
+            Some("") | None => None,
+            Some(t) => Some(self.parse_index(t, self.uvs.len(), "texture coordinate")?),
+        };
+
+        let normal = match parts.next() {
+            Some("") | None => None,
+            Some(n) => Some(self.parse_index(n, self.normals.len(), "normal")?),
+        };
+
+        if parts.next().is_some() {
+            return Err(self.error(format!("invalid face vertex '{}'", arg)));
+        }
+
+        Ok(VertexKey { position: position, uv: uv, normal: normal })
+    }
+
+    // OBJ indices are 1-based, negative indices are relative to the end of the
+    // elements declared so far.
+    fn parse_index(&self, token: &str, count: usize, kind: &str) -> Result<usize, ObjError> {
+        let index: i64 = token.parse()
+            .map_err(|_| self.error(format!("invalid {} index '{}'", kind, token)))?;
+
+        let resolved = if index > 0 {
+            index - 1
+        } else {
+            count as i64 + index
+        };
+
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(self.error(format!("{} index {} out of range ({} defined)", kind, index, count)));
+        }
+
+        return Ok(resolved as usize);
+    }
+
+    fn current_mesh(&mut self) -> usize {
+        let key = (self.current_group.clone(), self.current_material.clone());
+        if let Some(&index) = self.mesh_lookup.get(&key) {
+            return index;
+        }
+
+        let builder = match self.materials.get(&self.current_material) {
+            Some(material) => MeshBuilder::new(material.clone(), true),
+            None => MeshBuilder::new(self.default_material.clone(), false),
+        };
+
+        let index = self.meshes.len();
+        self.meshes.push(builder);
+        self.mesh_lookup.insert(key, index);
+
+        return index;
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    let io_error = |error| ObjError::Io { path: path.to_path_buf(), error: error };
+
+    let mut source = String::new();
+    File::open(path).map_err(&io_error)?
+        .read_to_string(&mut source).map_err(&io_error)?;
+
+    return Ok(source);
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(i) => &line[..i],
+        None => line,
+    }
+}
+
+fn parse_floats(args: &[&str], min: usize, max: usize) -> Result<Vec<f32>, String> {
+    if args.len() < min || args.len() > max {
+        let expected = if min == max { format!("{}", min) } else { format!("{} to {}", min, max) };
+        return Err(format!("expected {} values, got {}", expected, args.len()));
+    }
+
+    args.iter()
+        .map(|arg| arg.parse::<f32>().map_err(|_| format!("invalid number '{}'", arg)))
+        .collect()
+}
@@ -0,0 +1,961 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+extern crate toml;
+use self::toml::Value;
+use self::toml::value::Table;
+
+use math::*;
+use raytracer::*;
+use utils::hdr::*;
+use utils::image::*;
+use utils::obj::*;
+
+// Everything needed to render a scene file: the image settings, the world and
+// the integrator to render it with.
+pub struct Scene {
+    // Size of the rendered image, with room for both eyes of stereo cameras.
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub world: World,
+    pub integrator: Box<Integrator>,
+
+    // The scene file and every file it references.
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io { path: PathBuf, error: io::Error },
+    Syntax { path: PathBuf, message: String },
+    Invalid { path: PathBuf, key: String, message: String },
+    Obj(ObjError),
+    Image(ImageError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SceneError::Io { ref path, ref error } =>
+                write!(f, "{}: {}", path.display(), error),
+            SceneError::Syntax { ref path, ref message } =>
+                write!(f, "{}: {}", path.display(), message),
+            SceneError::Invalid { ref path, ref key, ref message } =>
+                write!(f, "{}: {}: {}", path.display(), key, message),
+            SceneError::Obj(ref error) =>
+                write!(f, "{}", error),
+            SceneError::Image(ref error) =>
+                write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+impl From<ObjError> for SceneError {
+    fn from(error: ObjError) -> SceneError {
+        SceneError::Obj(error)
+    }
+}
+
+impl From<ImageError> for SceneError {
+    fn from(error: ImageError) -> SceneError {
+        SceneError::Image(error)
+    }
+}
+
+// Loads a TOML scene description, see `scenes/cornell.toml` for an example.
+// Relative paths (meshes, environment maps) are resolved from the directory of the scene file.
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<Scene, SceneError> {
+    let path = path.as_ref();
+
+    let mut source = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut source))
+        .map_err(|error| SceneError::Io { path: path.to_path_buf(), error: error })?;
+
+    let value = source.parse::<Value>()
+        .map_err(|error| SceneError::Syntax { path: path.to_path_buf(), message: error.to_string() })?;
+
+    let mut parser = SceneParser { path: path, files: vec![path.to_path_buf()], meshes: HashMap::new() };
+    parser.parse(&value)
+}
+
+// Name of the node holding the top level objects and nodes.
+const ROOT_NODE: &str = "root";
+
+struct SceneParser<'a> {
+    path: &'a Path,
+    files: Vec<PathBuf>,
+    // Meshes already loaded, by file and material name, which further
+    // objects instance instead of loading them again.
+    meshes: HashMap<(PathBuf, Option<String>), Vec<Object>>,
+}
+
+// A value of the scene file along with its full key, e.g. `objects[2].radius`,
+// so that errors can point at the offending entry.
+struct Node<'a> {
+    value: &'a Value,
+    key: String,
+}
+
+impl<'a> SceneParser<'a> {
+    fn error<S: Into<String>>(&self, key: &str, message: S) -> SceneError {
+        SceneError::Invalid {
+            path: self.path.to_path_buf(),
+            key: key.to_string(),
+            message: message.into(),
+        }
+    }
+
+    fn type_error(&self, node: &Node, expected: &str) -> SceneError {
+        self.error(&node.key, format!("expected {}, found {}", expected, node.value.type_str()))
+    }
+
+    fn resolve(&self, file: &str) -> PathBuf {
+        self.path.parent().unwrap_or(Path::new("")).join(file)
+    }
+
+    fn parse(&mut self, value: &Value) -> Result<Scene, SceneError> {
+        let root = Node { value: value, key: String::new() };
+        self.check_keys(&root, &["image", "camera", "integrator", "textures", "materials", "lights", "objects",
+                                  "nodes"])?;
+
+        let image = self.get(&root, "image")?;
+        self.check_keys(&image, &["width", "height", "samples"])?;
+        let width = self.as_u32(&self.get(&image, "width")?)?;
+        let height = self.as_u32(&self.get(&image, "height")?)?;
+        let samples = match self.get_opt(&image, "samples")? {
+            Some(node) => self.as_u32(&node)?,
+            None => 8,
+        };
+
+        let camera = self.parse_camera(&self.get(&root, "camera")?, width, height)?;
+
+        let mut textures = HashMap::new();
+        if let Some(node) = self.get_opt(&root, "textures")? {
+            for (name, texture) in self.entries(&node)? {
+                textures.insert(name, self.parse_texture(&texture)?);
+            }
+        }
+
+        let mut materials = HashMap::new();
+        if let Some(node) = self.get_opt(&root, "materials")? {
+            for (name, material) in self.entries(&node)? {
+                materials.insert(name, self.parse_material(&material, &textures)?);
+            }
+        }
+
+        let mut lights = Vec::new();
+        if let Some(node) = self.get_opt(&root, "lights")? {
+            for light in self.elements(&node)? {
+                lights.extend(self.parse_light(&light)?);
+            }
+        }
+
+        // Top level objects and nodes hang from the root of the graph.
+        let mut graph = SceneNode::new(ROOT_NODE);
+        let mut names = HashSet::new();
+        names.insert(ROOT_NODE.to_string());
+        self.parse_node_contents(&root, &materials, &mut graph, &mut names)?;
+
+        let integrator = match self.get_opt(&root, "integrator")? {
+            Some(node) => self.parse_integrator(&node)?,
+            None => Box::new(DirectLightingIntegrator::new()),
+        };
+
+        let (width, height) = match camera.stereo().map(|stereo| stereo.layout) {
+            Some(StereoLayout::SideBySide) | Some(StereoLayout::Separate) => (2 * width, height),
+            Some(StereoLayout::TopBottom) => (width, 2 * height),
+            None => (width, height),
+        };
+
+        Ok(Scene {
+            width: width,
+            height: height,
+            samples: samples,
+            world: World::from_graph(graph, lights, camera),
+            integrator: integrator,
+            files: self.files.clone(),
+        })
+    }
+
+    fn parse_node(&mut self, node: &Node, materials: &HashMap<String, Material>,
+                  names: &mut HashSet<String>) -> Result<SceneNode, SceneError> {
+        self.check_keys(node, &["name", "translate", "rotate", "scale", "material", "visible", "objects",
+                                "nodes"])?;
+
+        let name_node = self.get(node, "name")?;
+        let name = self.as_str(&name_node)?;
+        if !names.insert(name.to_string()) {
+            return Err(self.error(&name_node.key, format!("there is already a node called '{}'", name)));
+        }
+
+        let mut result = SceneNode::new(name).with_visibility(self.bool_or(node, "visible", true)?);
+        if let Some(transform) = self.parse_transform(node)? {
+            result = result.with_transform(transform);
+        }
+        if let Some(material) = self.get_opt(node, "material")? {
+            result = result.with_material(self.material(&material, materials)?);
+        }
+
+        self.parse_node_contents(node, materials, &mut result, names)?;
+
+        return Ok(result);
+    }
+
+    // The `objects` and child `nodes` of `node`.
+    fn parse_node_contents(&mut self, node: &Node, materials: &HashMap<String, Material>,
+                           result: &mut SceneNode, names: &mut HashSet<String>) -> Result<(), SceneError> {
+        if let Some(objects) = self.get_opt(node, "objects")? {
+            for object in self.elements(&objects)? {
+                for object in self.parse_object(&object, materials)? {
+                    result.add_object(object);
+                }
+            }
+        }
+        if let Some(children) = self.get_opt(node, "nodes")? {
+            for child in self.elements(&children)? {
+                result.add_child(self.parse_node(&child, materials, names)?);
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn parse_integrator(&self, node: &Node) -> Result<Box<Integrator>, SceneError> {
+        let kind_node = self.get(node, "type")?;
+        let kind = self.as_str(&kind_node)?;
+
+        let result: Box<Integrator> = match kind {
+            "whitted" => {
+                self.check_keys(node, &["type"])?;
+                Box::new(DirectLightingIntegrator::new())
+            },
+            "path" => {
+                self.check_keys(node, &["type", "max_depth"])?;
+                Box::new(PathIntegrator::new(self.u32_or(node, "max_depth", 8)?))
+            },
+            _ => return Err(self.error(&kind_node.key, format!("unknown integrator type '{}'", kind))),
+        };
+
+        return Ok(result);
+    }
+
+    fn parse_camera(&self, node: &Node, width: u32, height: u32) -> Result<Camera, SceneError> {
+        self.check_keys(node, &["position", "target", "up", "roll", "projection", "vertical_fov",
+                                "horizontal_fov", "film_distance", "view_height", "fisheye_fov",
+                                "aperture", "focus_distance", "focus_point", "stereo", "shutter_open",
+                                "shutter_close"])?;
+
+        let position = self.as_vector3(&self.get(node, "position")?)?;
+        let target = self.as_vector3(&self.get(node, "target")?)?;
+        let mut builder = CameraBuilder::new(width, height)
+            .look_at(position, target)
+            .with_roll(self.f32_or(node, "roll", 0.0)?);
+        if let Some(up) = self.get_opt(node, "up")? {
+            builder = builder.with_up(self.as_vector3(&up)?);
+        }
+
+        // The field of view is given in one way at most.
+        let mut field_of_view: Option<Node> = None;
+        for name in ["vertical_fov", "horizontal_fov", "film_distance"].iter() {
+            if let Some(value) = self.get_opt(node, name)? {
+                if let Some(previous) = field_of_view {
+                    return Err(self.error(&value.key, format!("{} is already given", previous.key)));
+                }
+                builder = match *name {
+                    "vertical_fov" => builder.with_vertical_fov(self.as_angle(&value)?),
+                    "horizontal_fov" => builder.with_horizontal_fov(self.as_angle(&value)?),
+                    _ => builder.with_film_distance(self.as_positive_f32(&value)?),
+                };
+                field_of_view = Some(value);
+            }
+        }
+
+        let projection = match self.get_opt(node, "projection")? {
+            Some(value) => match self.as_str(&value)? {
+                "perspective" => Projection::Perspective,
+                "orthographic" => Projection::Orthographic {
+                    height: self.as_positive_f32(&self.get(node, "view_height")?)?,
+                },
+                "fisheye" => Projection::Fisheye { fov: self.f32_between(node, "fisheye_fov", 180.0, 0.0, 360.0)? },
+                "equirectangular" => Projection::Equirectangular,
+                name => return Err(self.error(&value.key, format!(
+                    "unknown projection '{}', expected one of: perspective, orthographic, fisheye, equirectangular",
+                    name))),
+            },
+            None => Projection::Perspective,
+        };
+
+        // Reject settings the projection would ignore.
+        let is_perspective = projection == Projection::Perspective;
+        let has_stereo = is_perspective || projection == Projection::Equirectangular;
+        let settings = [
+            (field_of_view, is_perspective, "perspective"),
+            (self.get_opt(node, "aperture")?, is_perspective, "perspective"),
+            (self.get_opt(node, "focus_distance")?, is_perspective, "perspective"),
+            (self.get_opt(node, "focus_point")?, is_perspective, "perspective"),
+            (self.get_opt(node, "view_height")?, matches!(projection, Projection::Orthographic { .. }), "orthographic"),
+            (self.get_opt(node, "fisheye_fov")?, matches!(projection, Projection::Fisheye { .. }), "fisheye"),
+            (self.get_opt(node, "stereo")?, has_stereo, "perspective or equirectangular"),
+        ];
+        for &(ref value, applies, name) in settings.iter() {
+            if let (Some(value), false) = (value, applies) {
+                return Err(self.error(&value.key, format!("only applies to the {} projection", name)));
+            }
+        }
+
+        if let Some(stereo) = self.get_opt(node, "stereo")? {
+            builder = builder.with_stereo(self.parse_stereo(&stereo, length(target - position))?);
+        }
+
+        let shutter_open = self.f32_or(node, "shutter_open", 0.0)?;
+        let shutter_close = self.f32_or(node, "shutter_close", shutter_open)?;
+        if shutter_close < shutter_open {
+            let key = child_key(&node.key, "shutter_close");
+            return Err(self.error(&key, format!("the shutter closes before it opens at {}", shutter_open)));
+        }
+        builder = builder.with_shutter(shutter_open, shutter_close);
+
+        let mut result = builder.with_projection(projection).build();
+
+        // The target is in focus unless told otherwise.
+        let aperture = self.f32_or(node, "aperture", 0.0)?;
+        if aperture < 0.0 {
+            let key = child_key(&node.key, "aperture");
+            return Err(self.error(&key, format!("expected a radius of zero or more, found {}", aperture)));
+        }
+        result.set_aperture(aperture);
+        match (self.get_opt(node, "focus_distance")?, self.get_opt(node, "focus_point")?) {
+            (Some(distance), None) => result.set_focus_distance(self.as_positive_f32(&distance)?),
+            (None, Some(point)) => result.focus_on(self.as_vector3(&point)?),
+            (None, None) => {},
+            (Some(_), Some(point)) => {
+                return Err(self.error(&point.key, "focus_distance and focus_point are exclusive"));
+            },
+        }
+
+        return Ok(result);
+    }
+
+    // The eyes converge on the camera target unless told otherwise.
+    fn parse_stereo(&self, node: &Node, target_distance: f32) -> Result<Stereo, SceneError> {
+        self.check_keys(node, &["interocular", "convergence", "convergence_distance", "layout"])?;
+
+        let convergence = match self.get_opt(node, "convergence")? {
+            Some(value) => match self.as_str(&value)? {
+                "parallel" => Convergence::Parallel,
+                "toe_in" => Convergence::ToeIn,
+                "off_axis" => Convergence::OffAxis,
+                name => return Err(self.error(&value.key, format!(
+                    "unknown convergence '{}', expected one of: parallel, toe_in, off_axis", name))),
+            },
+            None => Convergence::OffAxis,
+        };
+        let layout = match self.get_opt(node, "layout")? {
+            Some(value) => match self.as_str(&value)? {
+                "side_by_side" => StereoLayout::SideBySide,
+                "top_bottom" => StereoLayout::TopBottom,
+                "separate" => StereoLayout::Separate,
+                name => return Err(self.error(&value.key, format!(
+                    "unknown layout '{}', expected one of: side_by_side, top_bottom, separate", name))),
+            },
+            None => StereoLayout::SideBySide,
+        };
+        let convergence_distance = match self.get_opt(node, "convergence_distance")? {
+            Some(value) => self.as_positive_f32(&value)?,
+            None => max(target_distance, 1e-3),
+        };
+
+        Ok(Stereo {
+            interocular: self.as_positive_f32(&self.get(node, "interocular")?)?,
+            convergence: convergence,
+            convergence_distance: convergence_distance,
+            layout: layout,
+        })
+    }
+
+    fn parse_texture(&mut self, node: &Node) -> Result<Arc<Texture>, SceneError> {
+        let kind_node = self.get(node, "type")?;
+        let kind = self.as_str(&kind_node)?;
+
+        let result: Arc<Texture> = match kind {
+            "image" => {
+                self.check_keys(node, &["type", "file", "wrap"])?;
+                let wrap = match self.get_opt(node, "wrap")? {
+                    Some(wrap) => match self.as_str(&wrap)? {
+                        "repeat" => WrapMode::Repeat,
+                        "mirror" => WrapMode::Mirror,
+                        "clamp" => WrapMode::Clamp,
+                        name => return Err(self.error(&wrap.key, format!(
+                            "unknown wrap mode '{}', expected one of: repeat, mirror, clamp", name))),
+                    },
+                    None => WrapMode::Repeat,
+                };
+                let file = self.resolve(self.as_str(&self.get(node, "file")?)?);
+                self.files.push(file.clone());
+                let image = load_png(file)?;
+                Arc::new(ImageTexture::new(image.width, image.height, image.pixels, wrap))
+            },
+            "checker" => {
+                self.check_keys(node, &["type", "even", "odd", "frequency"])?;
+                let frequency = match self.get_opt(node, "frequency")? {
+                    Some(frequency) => self.as_positive_f32(&frequency)?,
+                    None => 8.0,
+                };
+                Arc::new(CheckerTexture::new(self.color_or(node, "even", Color::WHITE)?,
+                                             self.color_or(node, "odd", Color::BLACK)?, frequency))
+            },
+            "gradient" => {
+                self.check_keys(node, &["type", "start", "end", "from", "to"])?;
+                let start = self.as_vector3(&self.get(node, "start")?)?;
+                let end = self.as_vector3(&self.get(node, "end")?)?;
+                Arc::new(GradientTexture::new(start, end, self.color_or(node, "from", Color::BLACK)?,
+                                              self.color_or(node, "to", Color::WHITE)?))
+            },
+            "perlin" => {
+                self.check_keys(node, &["type", "scale", "octaves", "from", "to"])?;
+                self.noise_texture(node, NoiseKind::Perlin { octaves: self.u32_or(node, "octaves", 4)? })?
+            },
+            "worley" => {
+                self.check_keys(node, &["type", "scale", "from", "to"])?;
+                self.noise_texture(node, NoiseKind::Worley)?
+            },
+            _ => return Err(self.error(&kind_node.key, format!("unknown texture type '{}'", kind))),
+        };
+
+        return Ok(result);
+    }
+
+    fn noise_texture(&self, node: &Node, kind: NoiseKind) -> Result<Arc<Texture>, SceneError> {
+        let scale = match self.get_opt(node, "scale")? {
+            Some(scale) => self.as_positive_f32(&scale)?,
+            None => 1.0,
+        };
+
+        Ok(Arc::new(NoiseTexture::new(kind, scale, self.color_or(node, "from", Color::BLACK)?,
+                                      self.color_or(node, "to", Color::WHITE)?)))
+    }
+
+    // Materials without a type are diffuse, any material can emit light.
+    // Colors, emissions and roughnesses can also be the name of a texture.
+    fn parse_material(&self, node: &Node, textures: &HashMap<String, Arc<Texture>>) -> Result<Material, SceneError> {
+        let kind_node = self.get_opt(node, "type")?;
+        let kind = match kind_node {
+            Some(ref kind_node) => self.as_str(kind_node)?,
+            None => "diffuse",
+        };
+
+        let result = match kind {
+            "diffuse" => {
+                self.check_keys(node, &["type", "emission", "normal_map", "bump_map", "bump_scale", "albedo"])?;
+                Material::diffuse(self.color_texture(&self.get(node, "albedo")?, textures)?)
+            },
+            "mirror" => {
+                self.check_keys(node, &["type", "emission", "normal_map", "bump_map", "bump_scale", "reflectance"])?;
+                let reflectance = match self.get_opt(node, "reflectance")? {
+                    Some(reflectance) => self.color_texture(&reflectance, textures)?,
+                    None => Arc::new(ConstantTexture::new(Color::WHITE)),
+                };
+                Material::mirror(reflectance)
+            },
+            "conductor" => {
+                self.check_keys(node, &["type", "emission", "normal_map", "bump_map", "bump_scale",
+                                        "preset", "eta", "k", "roughness"])?;
+                let roughness = self.roughness(node, textures)?;
+                let (eta, k) = match self.get_opt(node, "preset")? {
+                    Some(preset) => match self.as_str(&preset)? {
+                        "gold" => GOLD,
+                        "copper" => COPPER,
+                        "aluminium" => ALUMINIUM,
+                        name => return Err(self.error(&preset.key, format!(
+                            "unknown conductor '{}', expected one of: gold, copper, aluminium", name))),
+                    },
+                    None => (self.as_color(&self.get(node, "eta")?)?, self.as_color(&self.get(node, "k")?)?),
+                };
+                Material::conductor(eta, k, roughness)
+            },
+            "dielectric" => {
+                self.check_keys(node, &["type", "emission", "normal_map", "bump_map", "bump_scale",
+                                        "ior", "roughness", "absorption"])?;
+                let ior = match self.get_opt(node, "ior")? {
+                    Some(ior) => self.as_positive_f32(&ior)?,
+                    None => 1.5,
+                };
+                Material::dielectric(ior, self.roughness(node, textures)?)
+                    .with_absorption(self.color_or(node, "absorption", Color::BLACK)?)
+            },
+            _ => {
+                let key = kind_node.map_or(node.key.clone(), |kind_node| kind_node.key);
+                return Err(self.error(&key, format!("unknown material type '{}'", kind)));
+            },
+        };
+
+        let mut result = result;
+        if let Some(emission) = self.get_opt(node, "emission")? {
+            result = match *emission.value {
+                Value::String(_) => result.with_emission_texture(self.texture(&emission, textures)?),
+                _ => result.with_emission(self.as_color(&emission)?),
+            };
+        }
+        if let Some(normal_map) = self.get_opt(node, "normal_map")? {
+            result = result.with_normal_map(self.texture(&normal_map, textures)?);
+        }
+        match (self.get_opt(node, "bump_map")?, self.get_opt(node, "bump_scale")?) {
+            (Some(bump_map), _) => {
+                result = result.with_bump_map(self.texture(&bump_map, textures)?, self.f32_or(node, "bump_scale", 1.0)?);
+            },
+            (None, Some(scale)) => return Err(self.error(&scale.key, "only applies along with a bump_map")),
+            (None, None) => {},
+        }
+
+        return Ok(result);
+    }
+
+    fn roughness(&self, node: &Node, textures: &HashMap<String, Arc<Texture>>) -> Result<Arc<Texture>, SceneError> {
+        if let Some(name) = self.get_opt(node, "roughness")? {
+            if let Value::String(_) = *name.value {
+                return self.texture(&name, textures);
+            }
+        }
+
+        let roughness = self.f32_between(node, "roughness", 0.0, 0.0, 1.0)?;
+        Ok(Arc::new(ConstantTexture::new(Color::grey(roughness))))
+    }
+
+    // A color, or the name of a texture.
+    fn color_texture(&self, node: &Node, textures: &HashMap<String, Arc<Texture>>) -> Result<Arc<Texture>, SceneError> {
+        if let Value::String(_) = *node.value {
+            return self.texture(node, textures);
+        }
+
+        Ok(Arc::new(ConstantTexture::new(self.as_color(node)?)))
+    }
+
+    fn texture(&self, node: &Node, textures: &HashMap<String, Arc<Texture>>) -> Result<Arc<Texture>, SceneError> {
+        let name = self.as_str(node)?;
+        match textures.get(name) {
+            Some(texture) => Ok(texture.clone()),
+            None => Err(self.error(&node.key, format!("unknown texture '{}'", name))),
+        }
+    }
+
+    // A sky comes with the sun lighting the scene directly.
+    fn parse_light(&mut self, node: &Node) -> Result<Vec<Box<Light>>, SceneError> {
+        let kind_node = self.get(node, "type")?;
+        let kind = self.as_str(&kind_node)?;
+
+        if kind == "sky" {
+            self.check_keys(node, &["type", "elevation", "azimuth", "turbidity", "intensity", "samples", "sun"])?;
+            let elevation = self.as_f32(&self.get(node, "elevation")?)?;
+            if !(0.0..=90.0).contains(&elevation) {
+                let key = child_key(&node.key, "elevation");
+                return Err(self.error(&key, format!("expected a number between 0 and 90, found {}", elevation)));
+            }
+
+            let sky = SkyLight::new(elevation, self.f32_or(node, "azimuth", 0.0)?,
+                                    self.f32_between(node, "turbidity", 3.0, 2.0, 10.0)?,
+                                    self.f32_or(node, "intensity", 1.0)?,
+                                    self.u32_or(node, "samples", AREA_LIGHT_SAMPLES)?);
+            let mut result: Vec<Box<Light>> = Vec::new();
+            if self.bool_or(node, "sun", true)? {
+                result.push(Box::new(sky.sun()));
+            }
+            result.push(Box::new(sky));
+            return Ok(result);
+        }
+
+        let result: Box<Light> = match kind {
+            "point" => {
+                self.check_keys(node, &["type", "position", "color", "intensity"])?;
+                let position = self.as_vector3(&self.get(node, "position")?)?;
+                Box::new(PointLight::new(position, self.color_or(node, "color", Color::WHITE)?,
+                                         self.f32_or(node, "intensity", 1.0)?))
+            },
+            "directional" => {
+                self.check_keys(node, &["type", "direction", "color", "intensity"])?;
+                let direction = self.as_vector3(&self.get(node, "direction")?)?;
+                Box::new(DirectionalLight::new(direction, self.color_or(node, "color", Color::WHITE)?,
+                                               self.f32_or(node, "intensity", 1.0)?))
+            },
+            "spot" => {
+                self.check_keys(node, &["type", "position", "direction", "color", "intensity",
+                                        "cone_angle", "falloff_start"])?;
+                let position = self.as_vector3(&self.get(node, "position")?)?;
+                let direction = self.as_vector3(&self.get(node, "direction")?)?;
+                let cone_angle = self.as_positive_f32(&self.get(node, "cone_angle")?)?;
+                let falloff_start = self.f32_or(node, "falloff_start", cone_angle)?;
+                Box::new(SpotLight::new(position, direction, self.color_or(node, "color", Color::WHITE)?,
+                                        self.f32_or(node, "intensity", 1.0)?, cone_angle, falloff_start))
+            },
+            "rect" => {
+                self.check_keys(node, &["type", "corner", "edge_u", "edge_v", "color", "intensity", "samples"])?;
+                let corner = self.as_vector3(&self.get(node, "corner")?)?;
+                let edge_u = self.as_vector3(&self.get(node, "edge_u")?)?;
+                let edge_v = self.as_vector3(&self.get(node, "edge_v")?)?;
+                Box::new(RectLight::new(corner, edge_u, edge_v, self.color_or(node, "color", Color::WHITE)?,
+                                        self.f32_or(node, "intensity", 1.0)?,
+                                        self.u32_or(node, "samples", AREA_LIGHT_SAMPLES)?))
+            },
+            "disk" => {
+                self.check_keys(node, &["type", "center", "normal", "radius", "color", "intensity", "samples"])?;
+                let center = self.as_vector3(&self.get(node, "center")?)?;
+                let normal = self.as_vector3(&self.get(node, "normal")?)?;
+                let radius = self.as_positive_f32(&self.get(node, "radius")?)?;
+                Box::new(DiskLight::new(center, normal, radius, self.color_or(node, "color", Color::WHITE)?,
+                                        self.f32_or(node, "intensity", 1.0)?,
+                                        self.u32_or(node, "samples", AREA_LIGHT_SAMPLES)?))
+            },
+            "sphere" => {
+                self.check_keys(node, &["type", "center", "radius", "color", "intensity", "samples"])?;
+                let center = self.as_vector3(&self.get(node, "center")?)?;
+                let radius = self.as_positive_f32(&self.get(node, "radius")?)?;
+                Box::new(SphereLight::new(center, radius, self.color_or(node, "color", Color::WHITE)?,
+                                          self.f32_or(node, "intensity", 1.0)?,
+                                          self.u32_or(node, "samples", AREA_LIGHT_SAMPLES)?))
+            },
+            "environment" => {
+                self.check_keys(node, &["type", "file", "color", "intensity", "rotation", "samples"])?;
+                let color = self.color_or(node, "color", Color::WHITE)?;
+                let intensity = self.f32_or(node, "intensity", 1.0)?;
+                match self.get_opt(node, "file")? {
+                    Some(file) => {
+                        let file = self.resolve(self.as_str(&file)?);
+                        self.files.push(file.clone());
+                        let map = load_hdr(file)?;
+                        Box::new(EnvironmentLight::new(map.width, map.height, map.pixels, color, intensity,
+                                                       self.f32_or(node, "rotation", 0.0)?,
+                                                       self.u32_or(node, "samples", AREA_LIGHT_SAMPLES)?))
+                    },
+                    None => Box::new(EnvironmentLight::uniform(color, intensity)),
+                }
+            },
+            _ => return Err(self.error(&kind_node.key, format!("unknown light type '{}'", kind))),
+        };
+
+        return Ok(vec![result]);
+    }
+
+    fn parse_object(&mut self, node: &Node, materials: &HashMap<String, Material>) -> Result<Vec<Object>, SceneError> {
+        let kind_node = self.get(node, "type")?;
+        let kind = self.as_str(&kind_node)?;
+
+        // Objects without a material are grey, unless a scene graph node
+        // gives them one. Meshes get their materials from the OBJ file, the
+        // object material is only used for faces without one.
+        let material = match self.get_opt(node, "material")? {
+            Some(name) => Some(self.material(&name, materials)?),
+            None => None,
+        };
+        let has_material = material.is_some();
+        let material = material.unwrap_or_else(|| Material::new(Color::grey(0.8)));
+
+        let mut result = match kind {
+            "plane" => {
+                self.check_keys(node, &["type", "material", "normal", "distance", "uv_origin", "uv_scale",
+                                        "translate", "rotate", "scale", "motion"])?;
+                let normal = safe_normalize(self.as_vector3(&self.get(node, "normal")?)?);
+                let distance = self.as_f32(&self.get(node, "distance")?)?;
+                let origin = match self.get_opt(node, "uv_origin")? {
+                    Some(origin) => self.as_vector3(&origin)?,
+                    None => -distance * normal,
+                };
+                let scale = match self.get_opt(node, "uv_scale")? {
+                    Some(scale) => self.as_positive_f32(&scale)?,
+                    None => 1.0,
+                };
+                let plane = Plane::new(normal, distance).with_mapping(origin, scale);
+                vec![Object::new(Box::new(plane), material.clone())]
+            },
+            "sphere" => {
+                self.check_keys(node, &["type", "material", "center", "radius",
+                                        "translate", "rotate", "scale", "motion"])?;
+                let center = self.as_vector3(&self.get(node, "center")?)?;
+                let radius = self.as_positive_f32(&self.get(node, "radius")?)?;
+                vec![make_sphere(center, radius, material.clone())]
+            },
+            "mesh" => {
+                self.check_keys(node, &["type", "material", "file", "translate", "rotate", "scale", "motion"])?;
+                let file = self.resolve(self.as_str(&self.get(node, "file")?)?);
+                let material_name = match self.get_opt(node, "material")? {
+                    Some(name) => Some(self.as_str(&name)?.to_string()),
+                    None => None,
+                };
+                let key = (file.clone(), material_name);
+                match self.meshes.get(&key) {
+                    Some(objects) => objects.clone(),
+                    None => {
+                        self.files.push(file.clone());
+                        let mut objects = load_obj(file, material.clone())?;
+                        if has_material {
+                            objects = objects.into_iter().map(|object| {
+                                if object.has_own_material() { object } else { object.with_material(material.clone()) }
+                            }).collect();
+                        }
+                        self.meshes.insert(key, objects.clone());
+                        objects
+                    },
+                }
+            },
+            _ => return Err(self.error(&kind_node.key, format!("unknown object type '{}'", kind))),
+        };
+
+        // Meshes already tell the faces with a library material apart.
+        if !has_material && kind != "mesh" {
+            result = result.into_iter().map(|object| object.with_default_material(material.clone())).collect();
+        }
+
+        if let Some(transform) = self.parse_transform(node)? {
+            result = result.into_iter().map(|object| object.with_transform(transform)).collect();
+        }
+        if let Some(motion) = self.get_opt(node, "motion")? {
+            let motion = self.parse_motion(&motion)?;
+            result = result.into_iter().map(|object| object.with_motion(motion.clone())).collect();
+        }
+
+        return Ok(result);
+    }
+
+    // Objects are scaled, then rotated around x, y and z by the angles in
+    // degrees of `rotate`, then translated. None when they stay in place.
+    fn parse_transform(&self, node: &Node) -> Result<Option<Transform>, SceneError> {
+        let mut result = None;
+
+        if let Some(scale) = self.get_opt(node, "scale")? {
+            let s = match *scale.value {
+                Value::Array(_) => self.as_vector3(&scale)?,
+                _ => {
+                    let s = self.as_f32(&scale)?;
+                    Vector3::new(s, s, s)
+                },
+            };
+            if s.x == 0.0 || s.y == 0.0 || s.z == 0.0 {
+                return Err(self.error(&scale.key, "scale factors must not be zero"));
+            }
+            result = Some(Transform::scale(s));
+        }
+
+        let mut then = |transform: Transform| {
+            result = Some(match result {
+                Some(previous) => previous.then(transform),
+                None => transform,
+            });
+        };
+
+        if let Some(rotate) = self.get_opt(node, "rotate")? {
+            let angles = self.as_vector3(&rotate)?;
+            then(Transform::rotate(Vector3::unit_x(), angles.x));
+            then(Transform::rotate(Vector3::unit_y(), angles.y));
+            then(Transform::rotate(Vector3::unit_z(), angles.z));
+        }
+        if let Some(translate) = self.get_opt(node, "translate")? {
+            then(Transform::translate(self.as_vector3(&translate)?));
+        }
+
+        return Ok(result);
+    }
+
+    // Keyframes of the transform of an object, applied after its own, e.g.
+    // `[{ time = 0.0 }, { time = 1.0, translate = [1, 0, 0], rotate = [0, 0, 90] }]`.
+    fn parse_motion(&self, node: &Node) -> Result<Motion, SceneError> {
+        let mut keyframes = Vec::new();
+        for keyframe in self.elements(node)? {
+            self.check_keys(&keyframe, &["time", "translate", "rotate", "scale"])?;
+            let time = self.as_f32(&self.get(&keyframe, "time")?)?;
+            let transform = self.parse_transform(&keyframe)?.unwrap_or_else(Transform::identity);
+            keyframes.push((time, transform));
+        }
+
+        if keyframes.is_empty() {
+            return Err(self.error(&node.key, "expected at least one keyframe"));
+        }
+
+        return Ok(Motion::new(keyframes));
+    }
+
+    fn material(&self, node: &Node, materials: &HashMap<String, Material>) -> Result<Material, SceneError> {
+        let name = self.as_str(node)?;
+        match materials.get(name) {
+            Some(material) => Ok(material.clone()),
+            None => Err(self.error(&node.key, format!("unknown material '{}'", name))),
+        }
+    }
+
+    fn get<'b>(&self, node: &Node<'b>, name: &str) -> Result<Node<'b>, SceneError> {
+        match self.get_opt(node, name)? {
+            Some(result) => Ok(result),
+            None => Err(self.error(&child_key(&node.key, name), "missing key")),
+        }
+    }
+
+    fn get_opt<'b>(&self, node: &Node<'b>, name: &str) -> Result<Option<Node<'b>>, SceneError> {
+        let table = self.as_table(node)?;
+        Ok(table.get(name).map(|value| Node { value: value, key: child_key(&node.key, name) }))
+    }
+
+    fn entries<'b>(&self, node: &Node<'b>) -> Result<Vec<(String, Node<'b>)>, SceneError> {
+        let table = self.as_table(node)?;
+        Ok(table.iter()
+            .map(|(name, value)| (name.clone(), Node { value: value, key: child_key(&node.key, name) }))
+            .collect())
+    }
+
+    fn elements<'b>(&self, node: &Node<'b>) -> Result<Vec<Node<'b>>, SceneError> {
+        let array = self.as_array(node)?;
+        Ok(array.iter().enumerate()
+            .map(|(i, value)| Node { value: value, key: format!("{}[{}]", node.key, i) })
+            .collect())
+    }
+
+    fn check_keys(&self, node: &Node, allowed: &[&str]) -> Result<(), SceneError> {
+        for name in self.as_table(node)?.keys() {
+            if !allowed.contains(&name.as_str()) {
+                let message = format!("unknown key, expected one of: {}", allowed.join(", "));
+                return Err(self.error(&child_key(&node.key, name), message));
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn as_table<'b>(&self, node: &Node<'b>) -> Result<&'b Table, SceneError> {
+        match *node.value {
+            Value::Table(ref table) => Ok(table),
+            _ => Err(self.type_error(node, "a table")),
+        }
+    }
+
+    fn as_array<'b>(&self, node: &Node<'b>) -> Result<&'b Vec<Value>, SceneError> {
+        match *node.value {
+            Value::Array(ref array) => Ok(array),
+            _ => Err(self.type_error(node, "an array")),
+        }
+    }
+
+    fn as_str<'b>(&self, node: &Node<'b>) -> Result<&'b str, SceneError> {
+        match *node.value {
+            Value::String(ref s) => Ok(s),
+            _ => Err(self.type_error(node, "a string")),
+        }
+    }
+
+    fn as_f32(&self, node: &Node) -> Result<f32, SceneError> {
+        match *node.value {
+            Value::Float(x) => Ok(x as f32),
+            Value::Integer(x) => Ok(x as f32),
+            _ => Err(self.type_error(node, "a number")),
+        }
+    }
+
+    fn as_bool(&self, node: &Node) -> Result<bool, SceneError> {
+        match *node.value {
+            Value::Boolean(x) => Ok(x),
+            _ => Err(self.type_error(node, "a boolean")),
+        }
+    }
+
+    fn bool_or(&self, node: &Node, name: &str, default: bool) -> Result<bool, SceneError> {
+        match self.get_opt(node, name)? {
+            Some(value) => self.as_bool(&value),
+            None => Ok(default),
+        }
+    }
+
+    fn f32_or(&self, node: &Node, name: &str, default: f32) -> Result<f32, SceneError> {
+        match self.get_opt(node, name)? {
+            Some(value) => self.as_f32(&value),
+            None => Ok(default),
+        }
+    }
+
+    fn f32_between(&self, node: &Node, name: &str, default: f32, low: f32, high: f32) -> Result<f32, SceneError> {
+        let result = self.f32_or(node, name, default)?;
+        if !(low..=high).contains(&result) {
+            let key = child_key(&node.key, name);
+            return Err(self.error(&key, format!("expected a number between {} and {}, found {}", low, high, result)));
+        }
+
+        return Ok(result);
+    }
+
+    fn u32_or(&self, node: &Node, name: &str, default: u32) -> Result<u32, SceneError> {
+        match self.get_opt(node, name)? {
+            Some(value) => self.as_u32(&value),
+            None => Ok(default),
+        }
+    }
+
+    fn color_or(&self, node: &Node, name: &str, default: Color) -> Result<Color, SceneError> {
+        match self.get_opt(node, name)? {
+            Some(value) => self.as_color(&value),
+            None => Ok(default),
+        }
+    }
+
+    fn as_positive_f32(&self, node: &Node) -> Result<f32, SceneError> {
+        let result = self.as_f32(node)?;
+        if result <= 0.0 {
+            return Err(self.error(&node.key, format!("expected a positive number, found {}", result)));
+        }
+
+        return Ok(result);
+    }
+
+    // Field of view in degrees.
+    fn as_angle(&self, node: &Node) -> Result<f32, SceneError> {
+        let result = self.as_f32(node)?;
+        if result <= 0.0 || result >= 180.0 {
+            return Err(self.error(&node.key, format!("expected an angle between 0 and 180 degrees, found {}", result)));
+        }
+
+        return Ok(result);
+    }
+
+    fn as_u32(&self, node: &Node) -> Result<u32, SceneError> {
+        match *node.value {
+            Value::Integer(x) if x > 0 && x <= u32::MAX as i64 => Ok(x as u32),
+            Value::Integer(x) => Err(self.error(&node.key, format!("expected a positive integer, found {}", x))),
+            _ => Err(self.type_error(node, "an integer")),
+        }
+    }
+
+    fn as_triple(&self, node: &Node) -> Result<[f32; 3], SceneError> {
+        let elements = self.elements(node)?;
+        if elements.len() != 3 {
+            return Err(self.error(&node.key, format!("expected 3 numbers, found {}", elements.len())));
+        }
+
+        let mut result = [0.0; 3];
+        for (i, element) in elements.iter().enumerate() {
+            result[i] = self.as_f32(element)?;
+        }
+
+        return Ok(result);
+    }
+
+    fn as_vector3(&self, node: &Node) -> Result<Vector3, SceneError> {
+        let v = self.as_triple(node)?;
+        Ok(Vector3::new(v[0], v[1], v[2]))
+    }
+
+    fn as_color(&self, node: &Node) -> Result<Color, SceneError> {
+        let v = self.as_triple(node)?;
+        Ok(Color::new(v[0], v[1], v[2]))
+    }
+}
+
+fn child_key(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", parent, name)
+    }
+}
@@ -0,0 +1,56 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+
+extern crate notify;
+use self::notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+// Watches a set of files for modifications. The parent directories are
+// watched rather than the files themselves, since most editors save by
+// replacing the file, which would silently end a watch on the file.
+pub struct FileWatcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    files: Vec<PathBuf>,
+}
+
+impl FileWatcher {
+    pub fn new(files: &[PathBuf]) -> notify::Result<FileWatcher> {
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+
+        let mut watched_files = Vec::new();
+        let mut watched_directories: Vec<PathBuf> = Vec::new();
+        for file in files.iter() {
+            let file = file.canonicalize()?;
+            let directory = file.parent().unwrap_or(Path::new("/")).to_path_buf();
+            if !watched_directories.contains(&directory) {
+                watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+                watched_directories.push(directory);
+            }
+            watched_files.push(file);
+        }
+
+        Ok(FileWatcher {
+            _watcher: watcher,
+            events: events,
+            files: watched_files,
+        })
+    }
+
+    // Returns true if any of the files was created, modified or removed since
+    // the last call. Never blocks.
+    pub fn changed(&self) -> bool {
+        let mut result = false;
+
+        for event in self.events.try_iter().flatten() {
+            if event.kind.is_access() {
+                continue;
+            }
+            if event.paths.iter().any(|path| self.files.contains(path)) {
+                result = true;
+            }
+        }
+
+        return result;
+    }
+}